@@ -92,10 +92,7 @@ impl BulkUpdaterOpts {
         // Then we get those that went successful
         let okay_checkout_packages: Vec<_> = out_packages
             .par_iter()
-            .filter_map(|(_, result)| match result {
-                Ok(checked_out_package) => Some(checked_out_package),
-                Err(_) => None,
-            })
+            .filter_map(|(_, result)| result.as_ref().ok())
             .collect();
 
         let attempted_package_cargo_update_before_revendors: Vec<_> = okay_checkout_packages
@@ -8,7 +8,7 @@
 
 use crate::services::{Service, Services};
 use libroast::common::Compression;
-use obs_service_cargo::cli::{Opts, Src, Vendor};
+use obs_service_cargo::cli::{ConfigFormat, LogFormat, ManifestMode, Opts, Src, Vendor};
 use std::path::{Path, PathBuf};
 use std::process;
 use std::process::Output;
@@ -288,6 +288,7 @@ pub fn attempt_cargo_update_before_revendor(
         let mut src: String = String::new();
         let mut tag: Option<String> = None;
         let mut filter = false; // Still experimental, so default=false for now
+        let mut filter_platform: Vec<String> = Vec::new();
         let mut respect_lockfile = true;
         let outdir = package_path.to_path_buf();
         for param in params.iter() {
@@ -324,6 +325,11 @@ pub fn attempt_cargo_update_before_revendor(
                         filter = val;
                     }
                 };
+                if name == "filter-platform" {
+                    if let Some(text) = &param.text {
+                        filter_platform.push(String::from(text));
+                    }
+                };
                 if name == "respect_lockfile" {
                     if let Some(val) = param.text.as_ref().and_then(|t| t.parse::<bool>().ok()) {
                         respect_lockfile = val;
@@ -346,20 +352,57 @@ pub fn attempt_cargo_update_before_revendor(
         }
 
         // ensure package path and source is joined
-        let srcpath = Src {
-            src: package_path.join(&src),
-        };
+        let srcpath = Src::new(&package_path.join(&src));
         let new_opts = Opts {
             src: srcpath.clone(),
-            compression: comp_type,
+            src_list: None,
+            src_sha256: None,
+            compression: comp_type.into(),
+            compression_level: None,
+            compression_threads: None,
+            max_archive_size: None,
+            reproducible: true,
             tag,
             cargotoml: cargotomls,
+            manifest_mode: ManifestMode::Merged,
+            jobs: None,
             update,
+            no_update: false,
             outdir,
+            vendor_dir_name: "vendor".to_string(),
+            prefix: None,
+            create_outdir: false,
+            tmpdir: None,
+            keep_workdir: None,
+            copy_exclude: Vec::new(),
             color: colorize,
+            quiet: false,
+            verbose: 0,
+            log_format: LogFormat::Text,
             i_accept_the_risk: accept_risks,
+            advisory_db: None,
+            audit_report: None,
             filter,
+            filter_platform,
+            no_dev_deps: false,
+            minimize: false,
+            exclude_crate: Vec::new(),
+            dedup: false,
             respect_lockfile,
+            locked: false,
+            require_lock: false,
+            offline: false,
+            frozen: false,
+            retries: 0,
+            timeout: None,
+            dry_run: false,
+            print_config: false,
+            config_fragment: Vec::new(),
+            registry_mirror: None,
+            config_format: ConfigFormat::Toml,
+            emit_checksum: false,
+            generate_completion: None,
+            command: None,
         };
         srcpath
             .run_vendor(&new_opts)
@@ -6,10 +6,14 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use std::collections::HashMap;
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::consts::{EXCLUDED_RUSTSECS, OPENSUSE_CARGO_AUDIT_DB};
+use crate::consts::{ADVISORY_DB_MAX_AGE_DAYS, EXCLUDED_RUSTSECS, OPENSUSE_CARGO_AUDIT_DB};
 use crate::errors::OBSCargoError;
 use crate::errors::OBSCargoErrorKind;
 
@@ -18,33 +22,251 @@ use rustsec::{
     Error as RustsecError, ErrorKind as RustsecErrorKind, Lockfile,
 };
 
+use serde::Serialize;
+
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn, Level};
 
-pub fn process_reports(reports: Vec<Report>) -> Result<(), OBSCargoError> {
+/// Days since the unix epoch for a date in the proleptic gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month_index = (i64::from(month) + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Opens the advisory database at `db_path`, or the one shipped by openSUSE
+/// if unset. We never fetch the database, so it has to exist and be recent.
+pub fn open_advisory_db(db_path: Option<&Path>) -> Result<Database, OBSCargoError> {
+    let db_path = db_path.unwrap_or(Path::new(OPENSUSE_CARGO_AUDIT_DB));
+    if !db_path.is_dir() {
+        error!(?db_path, "Advisory database not found");
+        return Err(OBSCargoError::new(
+            OBSCargoErrorKind::AuditError,
+            format!(
+                "advisory database not found at {}. The database is never fetched from the network. Install the cargo-audit-advisory-db package or point `--advisory-db` to a local clone of https://github.com/rustsec/advisory-db",
+                db_path.display()
+            ),
+        ));
+    }
+
+    let database = Database::open(db_path).map_err(|err| {
+        error!(?err, ?db_path, "Unable to open advisory database");
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::AuditError,
+            format!(
+                "unable to open advisory database at {}: {}",
+                db_path.display(),
+                err
+            ),
+            err,
+        )
+    })?;
+
+    let Some(newest) = database
+        .iter()
+        .map(|advisory| &advisory.metadata.date)
+        .max()
+    else {
+        error!(?db_path, "Advisory database is empty");
+        return Err(OBSCargoError::new(
+            OBSCargoErrorKind::AuditError,
+            format!(
+                "advisory database at {} has no advisories",
+                db_path.display()
+            ),
+        ));
+    };
+    let today = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| (d.as_secs() / 86400) as i64)
+        .unwrap_or_default();
+    let age = today - days_from_civil(i64::from(newest.year()), newest.month(), newest.day());
+    debug!(?db_path, newest = newest.as_str(), age, "Advisory database");
+    if age > ADVISORY_DB_MAX_AGE_DAYS {
+        error!(
+            ?db_path,
+            newest = newest.as_str(),
+            "Advisory database is stale"
+        );
+        return Err(OBSCargoError::new(
+            OBSCargoErrorKind::AuditError,
+            format!(
+                "advisory database at {} looks stale. Its newest advisory is from {}, {} days ago. Please update it",
+                db_path.display(),
+                newest.as_str(),
+                age
+            ),
+        ));
+    }
+    Ok(database)
+}
+
+/// Like [`open_advisory_db`], but every database is only loaded once per
+/// process, so vendoring several sources in one run shares it.
+pub fn shared_advisory_db(db_path: Option<&Path>) -> Result<Arc<Database>, OBSCargoError> {
+    static DATABASES: OnceLock<Mutex<HashMap<PathBuf, Arc<Database>>>> = OnceLock::new();
+    let key = db_path
+        .unwrap_or(Path::new(OPENSUSE_CARGO_AUDIT_DB))
+        .to_path_buf();
+    // Held while loading, so parallel runs wait for the first load instead
+    // of loading the same database again.
+    let mut databases = DATABASES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(database) = databases.get(&key) {
+        debug!(db_path = ?key, "Reusing loaded advisory database");
+        return Ok(Arc::clone(database));
+    }
+    let database = Arc::new(open_advisory_db(Some(&key))?);
+    databases.insert(key, Arc::clone(&database));
+    Ok(database)
+}
+
+fn is_accepted(id: &Id, accepted_risks: &[impl AsRef<str>]) -> bool {
+    accepted_risks
+        .iter()
+        .any(|accepted| accepted.as_ref() == id.as_str())
+}
+
+/// The accepted risks that match none of the advisories in `reports`.
+fn unmatched_accepted_risks<'a>(
+    reports: &[Report],
+    accepted_risks: &'a [impl AsRef<str>],
+) -> Vec<&'a str> {
+    accepted_risks
+        .iter()
+        .map(AsRef::as_ref)
+        .filter(|accepted| {
+            !reports
+                .iter()
+                .flat_map(|report| report.vulnerabilities.list.iter())
+                .any(|vuln| vuln.advisory.id.as_str() == *accepted)
+        })
+        .collect()
+}
+
+/// One vulnerability in the JSON audit report.
+#[derive(Debug, Serialize)]
+pub struct AuditFinding {
+    pub id: String,
+    pub package: String,
+    pub version: String,
+    pub title: String,
+    pub severity: Option<String>,
+    pub cvss: Option<f64>,
+    pub waived: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditReport {
+    pub passed: bool,
+    pub findings: Vec<AuditFinding>,
+}
+
+pub fn audit_findings(reports: &[Report], accepted_risks: &[impl AsRef<str>]) -> Vec<AuditFinding> {
+    reports
+        .iter()
+        .flat_map(|report| report.vulnerabilities.list.iter())
+        .map(|vuln| AuditFinding {
+            id: vuln.advisory.id.to_string(),
+            package: vuln.package.name.to_string(),
+            version: vuln.package.version.to_string(),
+            title: vuln.advisory.title.clone(),
+            severity: vuln
+                .advisory
+                .cvss
+                .as_ref()
+                .map(|base| base.severity().to_string()),
+            cvss: vuln.advisory.cvss.as_ref().map(|base| base.score().value()),
+            waived: is_accepted(&vuln.advisory.id, accepted_risks),
+        })
+        .collect()
+}
+
+/// Writes the findings of the audit as JSON to `path`. This is done whether
+/// or not the audit passes, so tooling can rely on the file being there.
+pub fn write_audit_report(
+    path: &Path,
+    reports: &[Report],
+    accepted_risks: &[impl AsRef<str>],
+) -> Result<(), OBSCargoError> {
+    let findings = audit_findings(reports, accepted_risks);
+    let report = AuditReport {
+        passed: findings.iter().all(|finding| finding.waived),
+        findings,
+    };
+    let json = serde_json::to_string_pretty(&report).map_err(|err| {
+        error!(?err, "Unable to serialize audit report");
+        OBSCargoError::new(
+            OBSCargoErrorKind::AuditError,
+            "unable to serialize audit report".to_string(),
+        )
+    })?;
+    fs::write(path, json).map_err(|err| {
+        error!(?err, ?path, "Unable to write audit report");
+        OBSCargoError::new(
+            OBSCargoErrorKind::AuditError,
+            format!("unable to write audit report to {}", path.display()),
+        )
+    })?;
+    info!("📝 Wrote audit report to {}", path.display());
+    Ok(())
+}
+
+/// Goes through the audit reports and fails if any vulnerability is left that
+/// is not listed in `accepted_risks`. Accepted ones are only reported as waived.
+pub fn process_reports(
+    reports: Vec<Report>,
+    accepted_risks: &[impl AsRef<str>],
+) -> Result<(), OBSCargoError> {
     let mut passed = true;
+    for accepted in unmatched_accepted_risks(&reports, accepted_risks) {
+        warn!(
+            "⚠️  Accepted risk {} does not match any advisory found by the audit. Is there a typo or can it be removed?",
+            accepted
+        );
+    }
 
     // Now actually analyse the report.
     for report in reports {
-        if report.vulnerabilities.found {
+        let (waived, actionable): (Vec<_>, Vec<_>) = report
+            .vulnerabilities
+            .list
+            .into_iter()
+            .partition(|vuln| is_accepted(&vuln.advisory.id, accepted_risks));
+
+        for vuln in waived {
+            info!(
+                advisory_id = %vuln.advisory.id,
+                crate_name = %vuln.package.name,
+                crate_version = %vuln.package.version,
+                "⚠️  Accepted risk - {} {} {} - {}",
+                vuln.advisory.id, vuln.package.name, vuln.package.version, vuln.advisory.title
+            );
+        }
+
+        if !actionable.is_empty() {
             passed = false;
 
-            if report.vulnerabilities.count == 1 {
-                warn!("⚠️  {} vulnerability found.", report.vulnerabilities.count);
+            if actionable.len() == 1 {
+                warn!("⚠️  {} vulnerability found.", actionable.len());
             } else {
-                warn!(
-                    "⚠️  {} vulnerabilities found.",
-                    report.vulnerabilities.count
-                );
+                warn!("⚠️  {} vulnerabilities found.", actionable.len());
             }
 
-            for vuln in report.vulnerabilities.list {
+            for vuln in actionable {
                 let score = vuln
                     .advisory
                     .cvss
                     .map(|base| base.score().value().to_string())
                     .unwrap_or_else(|| "unset".to_string());
                 let id = vuln.advisory.id;
+                let title = vuln.advisory.title;
                 let name = vuln.package.name;
                 let version = vuln.package.version;
 
@@ -54,7 +276,13 @@ pub fn process_reports(reports: Vec<Report>) -> Result<(), OBSCargoError> {
                     category.push(' ');
                 }
 
-                warn!("- {id} {name} {version} - categories {category}- cvss {score}");
+                warn!(
+                    advisory_id = %id,
+                    crate_name = %name,
+                    crate_version = %version,
+                    cvss = %score,
+                    "- {id} {name} {version} - {title} - categories {category}- cvss {score}"
+                );
             }
 
             error!("⚠️  You must action these before submitting this package.");
@@ -73,23 +301,21 @@ pub fn process_reports(reports: Vec<Report>) -> Result<(), OBSCargoError> {
 }
 
 pub fn perform_cargo_audit(
+    database: &Database,
     lockfiles: &[impl AsRef<Path>],
     exclude_ids: &[impl AsRef<str>],
 ) -> Result<Vec<Report>, RustsecError> {
-    // Setup our exclusions.
+    // Setup our exclusions. Accepted risks stay in the report so that
+    // `process_reports` can list them as waived.
     let ignore = EXCLUDED_RUSTSECS
         .iter()
         .copied()
-        .chain(exclude_ids.iter().map(|as_str| {
-            let s = as_str.as_ref();
-            info!("⚠️  Accepted risk - {}", s);
-            s
-        }))
         .map(Id::from_str)
         .collect::<Result<Vec<_>, _>>()?;
+    for id in exclude_ids {
+        Id::from_str(id.as_ref())?;
+    }
 
-    let db_path: PathBuf = OPENSUSE_CARGO_AUDIT_DB.into();
-    let database = Database::open(db_path.as_path())?;
     let report_settings = ReportSettings {
         ignore,
         ..Default::default()
@@ -100,7 +326,7 @@ pub fn perform_cargo_audit(
         .map(|lockfile_ref| {
             let lockfile_path: &Path = lockfile_ref.as_ref();
             Lockfile::load(lockfile_path)
-                .map(|lockfile| Report::generate(&database, &lockfile, &report_settings))
+                .map(|lockfile| Report::generate(database, &lockfile, &report_settings))
                 .map_err(|cargo_lock_err| {
                     error!(?cargo_lock_err);
                     RustsecError::new(RustsecErrorKind::BadParam, &cargo_lock_err)
@@ -108,3 +334,191 @@ pub fn perform_cargo_audit(
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A date `days` days before today, as used in advisories.
+    fn days_ago(days: i64) -> String {
+        let today = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| (d.as_secs() / 86400) as i64)
+            .unwrap();
+        let target = today - days;
+        // Walk from a date we know until the day count matches.
+        let (mut year, mut month, mut day) = (1970, 1, 1);
+        while days_from_civil(year + 1, 1, 1) <= target {
+            year += 1;
+        }
+        while month < 12 && days_from_civil(year, month + 1, 1) <= target {
+            month += 1;
+        }
+        while days_from_civil(year, month, day) < target {
+            day += 1;
+        }
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+
+    fn fixture() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/advisory-db")
+    }
+
+    /// The audit of a lockfile depending on vulnerable crates of the fixture
+    /// database, `vulnerable.lock` on foo and `mixed.lock` on foo and bar.
+    fn fixture_reports(lockfile: &str) -> Vec<Report> {
+        let database = Database::open(&fixture()).unwrap();
+        let lockfile = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/audit")
+            .join(lockfile);
+        perform_cargo_audit(&database, &[lockfile], &[] as &[&str]).unwrap()
+    }
+
+    /// A copy of the fixture with the advisory dated `date`.
+    fn fixture_dated(date: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let advisory = fs::read_to_string(fixture().join("crates/foo/RUSTSEC-2019-0001.md"))
+            .unwrap()
+            .replace("2019-01-01", date);
+        fs::create_dir_all(dir.path().join("crates/foo")).unwrap();
+        fs::write(dir.path().join("crates/foo/RUSTSEC-2019-0001.md"), advisory).unwrap();
+        dir
+    }
+
+    #[test]
+    fn days_from_civil_known_dates() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(days_from_civil(2024, 2, 29), 19782);
+    }
+
+    #[test]
+    fn missing_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = open_advisory_db(Some(&dir.path().join("missing"))).unwrap_err();
+        assert!(matches!(err.kind(), OBSCargoErrorKind::AuditError));
+        assert!(err.to_string().contains("not found"), "{}", err);
+    }
+
+    #[test]
+    fn empty_database() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("crates")).unwrap();
+        let err = open_advisory_db(Some(dir.path())).unwrap_err();
+        assert!(matches!(err.kind(), OBSCargoErrorKind::AuditError));
+        assert!(err.to_string().contains("has no advisories"), "{}", err);
+    }
+
+    #[test]
+    fn stale_database() {
+        let err = open_advisory_db(Some(&fixture())).unwrap_err();
+        assert!(matches!(err.kind(), OBSCargoErrorKind::AuditError));
+        assert!(err.to_string().contains("looks stale"), "{}", err);
+        assert!(err.to_string().contains("2019-01-01"), "{}", err);
+
+        let db = fixture_dated(&days_ago(ADVISORY_DB_MAX_AGE_DAYS + 1));
+        let err = open_advisory_db(Some(db.path())).unwrap_err();
+        assert!(err.to_string().contains("looks stale"), "{}", err);
+    }
+
+    #[test]
+    fn fresh_database() {
+        for age in [0, ADVISORY_DB_MAX_AGE_DAYS] {
+            let db = fixture_dated(&days_ago(age));
+            let database = open_advisory_db(Some(db.path())).unwrap();
+            assert_eq!(database.iter().count(), 1);
+        }
+    }
+
+    #[test]
+    fn vulnerability_needs_action() {
+        let reports = fixture_reports("vulnerable.lock");
+        let found: Vec<String> = reports
+            .iter()
+            .flat_map(|report| report.vulnerabilities.list.iter())
+            .map(|vuln| vuln.advisory.id.to_string())
+            .collect();
+        assert_eq!(found, ["RUSTSEC-2019-0001"]);
+        let err = process_reports(reports, &[] as &[&str]).unwrap_err();
+        assert!(matches!(err.kind(), OBSCargoErrorKind::AuditNeedsAction));
+    }
+
+    #[test]
+    fn accepted_vulnerability_waived() {
+        process_reports(fixture_reports("vulnerable.lock"), &["RUSTSEC-2019-0001"]).unwrap();
+        // Waiving another advisory does not help.
+        let err = process_reports(fixture_reports("vulnerable.lock"), &["RUSTSEC-2019-0002"])
+            .unwrap_err();
+        assert!(matches!(err.kind(), OBSCargoErrorKind::AuditNeedsAction));
+    }
+
+    #[test]
+    fn audit_report_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.json");
+        write_audit_report(
+            &path,
+            &fixture_reports("mixed.lock"),
+            &["RUSTSEC-2019-0001"],
+        )
+        .unwrap();
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(report["passed"], false);
+        let findings = report["findings"].as_array().unwrap();
+        assert_eq!(findings.len(), 2);
+        let finding = |id: &str| {
+            findings
+                .iter()
+                .find(|finding| finding["id"] == id)
+                .unwrap()
+                .as_object()
+                .unwrap()
+        };
+
+        let vulnerable = finding("RUSTSEC-2019-0002");
+        let mut keys: Vec<&str> = vulnerable.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            ["cvss", "id", "package", "severity", "title", "version", "waived"]
+        );
+        assert_eq!(vulnerable["package"], "bar");
+        assert_eq!(vulnerable["version"], "0.2.0");
+        assert_eq!(vulnerable["title"], "Bar is worse");
+        assert_eq!(vulnerable["severity"], "critical");
+        assert_eq!(vulnerable["cvss"], 9.8);
+        assert_eq!(vulnerable["waived"], false);
+
+        let waived = finding("RUSTSEC-2019-0001");
+        assert_eq!(waived["package"], "foo");
+        assert_eq!(waived["version"], "0.1.0");
+        assert_eq!(waived["severity"], serde_json::Value::Null);
+        assert_eq!(waived["cvss"], serde_json::Value::Null);
+        assert_eq!(waived["waived"], true);
+
+        // With everything waived the audit passes.
+        let accepted = ["RUSTSEC-2019-0001", "RUSTSEC-2019-0002"];
+        write_audit_report(&path, &fixture_reports("mixed.lock"), &accepted).unwrap();
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(report["passed"], true);
+    }
+
+    #[test]
+    fn unmatched_accepted_risk_reported() {
+        let reports = fixture_reports("vulnerable.lock");
+        let accepted = [
+            "RUSTSEC-2019-0001",
+            "RUSTSEC-2019-0002",
+            "RUSTSEC-2024-9999",
+        ];
+        assert_eq!(
+            unmatched_accepted_risks(&reports, &accepted),
+            ["RUSTSEC-2019-0002", "RUSTSEC-2024-9999"]
+        );
+        // It is only a warning, the matching one is still waived.
+        process_reports(reports, &accepted).unwrap();
+        assert!(unmatched_accepted_risks(&[], &[] as &[&str]).is_empty());
+    }
+}
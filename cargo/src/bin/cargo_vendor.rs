@@ -7,8 +7,9 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 // SPDX-License-Identifier: MPL-2.0
 
-use clap::Parser;
-use obs_service_cargo::cli::{self, Vendor};
+use clap::{CommandFactory, FromArgMatches};
+use clap_complete::Shell;
+use obs_service_cargo::cli;
 
 use std::io;
 use std::io::IsTerminal;
@@ -19,12 +20,32 @@ use tracing::{debug, error, info, warn, Level};
 use tracing_subscriber::EnvFilter;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = cli::Opts::parse();
+    // Completions are generated without the otherwise required arguments, so
+    // look for the flag before the real parse fails on them.
+    if let Ok(matches) = cli::Opts::command().ignore_errors(true).try_get_matches() {
+        if let Some(shell) = matches.get_one::<Shell>("generate_completion") {
+            cli::generate_completion(*shell, &mut io::stdout());
+            return Ok(());
+        }
+    }
+
+    let matches = cli::Opts::command().get_matches();
+    if let Some(("inspect", sub_matches)) = matches.subcommand() {
+        let src = cli::Src::from_arg_matches(sub_matches).unwrap_or_else(|err| err.exit());
+        if let Err(err) = src.inspect(&mut io::stdout()) {
+            eprintln!("{}", err);
+            for line in err.details() {
+                eprintln!("  {}", line);
+            }
+            std::process::exit(err.kind().exit_code());
+        }
+        return Ok(());
+    }
+    let args = cli::Opts::parse_checked(&matches);
 
     let terminfodb = Database::from_env().map_err(|e| {
         error!(err = ?e, "Unable to access terminfo db. This is a bug!");
-        io::Error::new(
-            io::ErrorKind::Other,
+        io::Error::other(
             "Unable to access terminfo db. This is a bug! Setting color option to false!",
         )
     });
@@ -34,14 +55,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         Err(_) => false,
     };
 
-    let to_color = std::io::stdout().is_terminal()
-        && match &args.color {
-            clap::ColorChoice::Auto => is_termcolorsupported,
-            clap::ColorChoice::Always => true,
-            clap::ColorChoice::Never => false,
-        };
+    let to_color = args.use_color(std::io::stdout().is_terminal(), is_termcolorsupported);
 
-    let filter_layer = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let filter_layer =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(args.log_level()));
 
     let builder = tracing_subscriber::fmt()
         .with_level(true)
@@ -55,14 +72,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         builder
     };
 
-    builder.init();
+    match args.log_format {
+        cli::LogFormat::Text => builder.init(),
+        cli::LogFormat::Json => builder.json().with_ansi(false).init(),
+    }
 
-    info!("🎢 Starting OBS Service Cargo Vendor.");
+    info!(
+        src = %args.src.src.display(),
+        outdir = %args.outdir.display(),
+        "🎢 Starting OBS Service Cargo Vendor."
+    );
     debug!(?args);
 
-    warn!("⚠️  Cargo Vendor has been rewritten in rust!");
-    eprintln!(
-        r#"
+    if !args.quiet {
+        warn!("⚠️  Cargo Vendor has been rewritten in rust!");
+        eprintln!(
+            r#"
 This rewrite introduces some small changes to how vendoring functions for your package.
 
 * cargo_config is no longer created - it's part of the vendor.tar now
@@ -75,10 +100,20 @@ This rewrite introduces some small changes to how vendoring functions for your p
 * cargo_audit is now part of cargo_vendor, meaning you don't have to configure it separately
 
 "#
-    );
-
-    Ok(args.src.run_vendor(&args).map_err(|err| {
-        error!("{}", err);
-        err
-    })?)
+        );
+    }
+
+    match obs_service_cargo::vendor(&args) {
+        Ok(output) => {
+            debug!(?output);
+            Ok(())
+        }
+        Err(err) => {
+            error!("{}", err);
+            for line in err.details() {
+                error!("  {}", line);
+            }
+            std::process::exit(err.kind().exit_code());
+        }
+    }
 }
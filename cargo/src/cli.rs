@@ -6,6 +6,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use std::borrow::Cow;
 use std::io;
 use std::path::{Path, PathBuf};
 
@@ -13,16 +14,18 @@ use crate::consts::VENDOR_PATH_PREFIX;
 use crate::errors::OBSCargoError;
 use crate::errors::OBSCargoErrorKind;
 use crate::utils;
+use crate::utils::compress::CompressionExt;
+use crate::vendor::VendorOutput;
 use libroast::common::Compression;
 use libroast::common::{SupportedFormat, UnsupportedFormat};
 
-use clap::Parser;
-use libroast::decompress;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use clap_complete::Shell;
 
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn, Level};
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(
     author,
     name = "cargo_vendor",
@@ -31,18 +34,57 @@ use tracing::{debug, error, info, trace, warn, Level};
     after_long_help = "Set verbosity and tracing through `RUST_LOG` environmental variable e.g. `RUST_LOG=trace`
 
 Bugs can be reported on GitHub: https://github.com/openSUSE/obs-service-cargo_vendor/issues",
-    max_term_width = 120
+    max_term_width = 120,
+    subcommand_negates_reqs = true,
+    // `src` is shared with `inspect`, which has no `src-list`. The default is
+    // never used, it only lets `src-list` stand in for `src`.
+    mut_arg("src", |arg| arg.required(false).required_unless_present("src_list").default_value(".").hide_default_value(true))
 )]
 pub struct Opts {
     #[clap(flatten)]
     pub src: Src,
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "src",
+        help = "Vendor every source listed in FILE, one per line, to its own tarball. A line may name the tag after the source, otherwise the file name without the tarball extension is used. Blank lines and lines starting with `#` are ignored. Sources are vendored in parallel, see `jobs`."
+    )]
+    pub src_list: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "HEX",
+        value_parser = parse_sha256,
+        conflicts_with = "src_list",
+        help = "Abort before extracting if the SHA-256 of the `src` tarball is not HEX."
+    )]
+    pub src_sha256: Option<String>,
     #[arg(
         long,
         value_enum,
         default_value_t,
-        help = "What compression algorithm to use. Set to `not` if you just want a normal tarball with no compression."
+        help = "What compression algorithm to use. Set to `not` if you just want a normal tarball with no compression, or to `inherit` to use the compression of the `src` tarball."
+    )]
+    pub compression: CompressionChoice,
+    #[arg(
+        long,
+        help = "Compression level to use. Valid levels depend on the compression algorithm: gz 0-9, xz 0-9, zst 1-22, bz2 1-9. If unset, a default level suited to the algorithm is used."
+    )]
+    pub compression_level: Option<u32>,
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Number of worker threads used for zst and xz compression. Defaults to the number of available CPUs."
     )]
-    pub compression: Compression,
+    pub compression_threads: Option<u32>,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Fail as soon as the vendor tarball grows beyond this many bytes, instead of filling the disk."
+    )]
+    pub max_archive_size: Option<u64>,
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, help = "Create reproducible tarballs. Entries are sorted and get fixed timestamps and ownership.")]
+    pub reproducible: bool,
     #[arg(
         long,
         help = "Tag some files for multi-vendor and multi-cargo_config projects"
@@ -50,28 +92,496 @@ pub struct Opts {
     pub tag: Option<String>,
     #[arg(long, help = "Other cargo manifest files to sync with during vendor")]
     pub cargotoml: Vec<PathBuf>,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "How to vendor multiple `cargotoml` manifests. `merged` puts all dependencies into one vendor tarball. `separate` creates a vendor tarball per manifest next to it, tagged with the manifest's directory."
+    )]
+    pub manifest_mode: ManifestMode,
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Number of manifests vendored at the same time with `manifest-mode` set to `separate`, or of sources with `src-list`. Defaults to the number of available CPUs."
+    )]
+    pub jobs: Option<u32>,
     #[arg(long, default_value_t = true, action = clap::ArgAction::Set, help = "Update dependencies or not")]
     pub update: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        action = clap::ArgAction::Set,
+        help = "Same as `--update false`. Vendor exactly what the lockfile pins."
+    )]
+    pub no_update: bool,
     #[arg(long, default_value_t = false, action = clap::ArgAction::Set, help = "EXPERIMENTAL: Reduce vendor-tarball size by filtering out non-Linux dependencies.")]
     pub filter: bool,
+    #[arg(
+        long,
+        value_name = "TRIPLE",
+        help = "Only vendor dependencies needed for this target triple. Can be specified multiple times. Like `filter`, this uses cargo-vendor-filterer and replaces its default list of platforms."
+    )]
+    pub filter_platform: Vec<String>,
+    #[arg(long, default_value_t = false, action = clap::ArgAction::Set, help = "Do not vendor crates that are only dev-dependencies, e.g. for tests and benchmarks. Like `filter`, this uses cargo-vendor-filterer.")]
+    pub no_dev_deps: bool,
+    #[arg(long, default_value_t = false, action = clap::ArgAction::Set, help = "Remove files not needed to build the vendored crates, like docs, tests and benchmarks. Crates with a build script are kept as they are.")]
+    pub minimize: bool,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Leave the crate NAME out of the vendor directory, e.g. because the distribution packages it. The build has to provide it, for example through `[patch.crates-io]` in a `config-fragment`. Can be specified multiple times."
+    )]
+    pub exclude_crate: Vec<String>,
+    #[arg(long, default_value_t = false, action = clap::ArgAction::Set, help = "Store files with identical contents only once in the vendor tarball. The copies are added as hard links to the first one.")]
+    pub dedup: bool,
     #[arg(long, help = "Where to output vendor.tar* and cargo_config")]
     pub outdir: PathBuf,
+    #[arg(
+        long,
+        default_value = "vendor",
+        value_name = "NAME",
+        value_parser = parse_vendor_dir_name,
+        help = "Name of the directory the dependencies are vendored to, inside the tarball and in the cargo config."
+    )]
+    pub vendor_dir_name: String,
+    #[arg(
+        long,
+        value_name = "PATH",
+        value_parser = parse_prefix,
+        help = "Store the entries of the vendor tarball under this relative path, e.g. `myproject` to get `myproject/vendor/...`. By default they are stored relative to the project root."
+    )]
+    pub prefix: Option<PathBuf>,
+    #[arg(long, default_value_t = false, action = clap::ArgAction::Set, help = "Create `outdir` if it does not exist yet.")]
+    pub create_outdir: bool,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Directory for the temporary working copy of the sources. Defaults to `TMPDIR` or /tmp. Point this at a large disk for big projects."
+    )]
+    pub tmpdir: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Work in this directory instead of a temporary one and keep it afterwards, also when vendoring fails. Lets you inspect the extracted and vendored sources. Must be empty if it exists. Cannot be combined with `src-list`, whose sources are vendored in parallel."
+    )]
+    pub keep_workdir: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        value_parser = parse_copy_exclude,
+        help = "Leave out files matching PATTERN when `src` is a directory, in addition to .git, .hg, .svn, the top-level target directory and *.orig files. Patterns work like in .gitignore. Can be specified multiple times."
+    )]
+    pub copy_exclude: Vec<String>,
     #[arg(
         long,
         default_value = "auto",
         default_missing_value = "always",
         value_name = "WHEN",
-        help = "Whether WHEN to color output or not"
+        help = "Whether WHEN to color log output or not. `auto` colors it if stdout is a terminal that supports colors and NO_COLOR is not set."
     )]
     pub color: clap::ColorChoice,
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        action = clap::ArgAction::Set,
+        conflicts_with = "verbose",
+        help = "Only log warnings and errors. An explicit `RUST_LOG` still takes precedence."
+    )]
+    pub quiet: bool,
+    #[arg(
+        short,
+        long,
+        action = clap::ArgAction::Count,
+        help = "Log more. Once for debug, twice for trace output. An explicit `RUST_LOG` still takes precedence."
+    )]
+    pub verbose: u8,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "Format of the log. `json` writes one JSON object per event, with fields like the crate name or advisory ID."
+    )]
+    pub log_format: LogFormat,
 
     #[arg(
         long,
+        value_parser = parse_rustsec_id,
         help = "A list of rustsec-id's to ignore. By setting this value, you acknowledge that this issue does not affect your package and you should be exempt from resolving it."
     )]
     pub i_accept_the_risk: Vec<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Path to a local copy of the RustSec advisory database used for the audit. It is never fetched from the network, and vendoring fails if its newest advisory is older than 180 days. Defaults to /usr/share/cargo-audit-advisory-db."
+    )]
+    pub advisory_db: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write the audit findings as JSON to this file. The file is written even if the audit passes."
+    )]
+    pub audit_report: Option<PathBuf>,
     #[arg(long, default_value_t = true, action = clap::ArgAction::Set, help = "Respect lockfile or not if it exists. Otherwise, regenerate the lockfile and try to respect the lockfile.")]
     pub respect_lockfile: bool,
+    #[arg(long, default_value_t = false, action = clap::ArgAction::Set, help = "Require an existing Cargo.lock that is up to date with the manifests. Dependencies are not updated and the vendor fails if the lockfile would need changes.")]
+    pub locked: bool,
+    #[arg(long, default_value_t = false, action = clap::ArgAction::Set, help = "Fail if a Cargo.lock is missing instead of generating one. Unlike `locked`, an existing lockfile may still be updated.")]
+    pub require_lock: bool,
+    #[arg(long, default_value_t = false, action = clap::ArgAction::Set, help = "Run cargo offline. Only crates from the local cargo cache are used and the network is never accessed.")]
+    pub offline: bool,
+    #[arg(long, default_value_t = false, action = clap::ArgAction::Set, help = "Like `locked` and `offline` together, as cargo's --frozen. Cargo.lock is not touched and the network is never accessed. Cannot be combined with `--update true`.")]
+    pub frozen: bool,
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "How often to retry a cargo command that failed because of the network. Each retry waits twice as long as the one before. Resolution errors are never retried."
+    )]
+    pub retries: u32,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Kill a cargo command running longer than this. It counts as a network failure and is retried if `retries` allows."
+    )]
+    pub timeout: Option<u64>,
+    #[arg(long, default_value_t = false, action = clap::ArgAction::Set, help = "Only find the manifests and log what would be vendored and which tarballs would be created. Nothing is written to `outdir`.")]
+    pub dry_run: bool,
+    #[arg(long, default_value_t = false, action = clap::ArgAction::Set, help = "Also print the generated cargo config to stdout. It is still added to the vendor tarball.")]
+    pub print_config: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Merge the TOML in FILE into the generated cargo config, e.g. for `[net]` or `[http]` settings. The source replacement wins if both set the same key. Can be specified multiple times, later files win over earlier ones."
+    )]
+    pub config_fragment: Vec<PathBuf>,
+    #[arg(
+        long,
+        value_name = "URL",
+        value_parser = parse_registry_url,
+        help = "Make the generated cargo config replace crates.io with the registry at URL, e.g. `sparse+https://mirror.example.com/index/`, instead of the vendor directory. Crates are still vendored and audited. Git sources keep using the vendor directory."
+    )]
+    pub registry_mirror: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "File the cargo config is written to. `toml` writes `.cargo/config.toml`. `legacy` writes `.cargo/config` for cargo older than 1.39."
+    )]
+    pub config_format: ConfigFormat,
+    #[arg(long, default_value_t = false, action = clap::ArgAction::Set, help = "Write the SHA-256 checksum of the vendor tarball to `<tarball>.sha256` and the vendored crates to `<tarball>.crates` next to it.")]
+    pub emit_checksum: bool,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "SHELL",
+        hide = true,
+        exclusive = true,
+        help = "Print shell completions for SHELL to stdout and exit."
+    )]
+    pub generate_completion: Option<Shell>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Print the compression and top-level layout of the sources without
+    /// vendoring them.
+    Inspect(Src),
+}
+
+/// Writes the completions of `cargo_vendor` for `shell` to `out`.
+pub fn generate_completion(shell: Shell, out: &mut impl io::Write) {
+    let mut command = Opts::command();
+    clap_complete::generate(shell, &mut command, "cargo_vendor", out);
+}
+
+/// How multiple manifests given with `--cargotoml` are vendored.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ManifestMode {
+    /// One vendor tree and tarball shared by all manifests.
+    #[default]
+    Merged,
+    /// A vendor tree and tarball for each manifest.
+    Separate,
+}
+
+/// How log events are written.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// The `compression` option, which may also be taken from the source.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompressionChoice {
+    Gz,
+    Xz,
+    #[default]
+    Zst,
+    Bz2,
+    Not,
+    /// The compression of the source tarball. Sources that are a directory
+    /// get the default.
+    Inherit,
+}
+
+impl CompressionChoice {
+    /// The compression to use for a source compressed with `source`, which
+    /// is `None` if the source is a directory.
+    pub fn resolve(self, source: Option<Compression>) -> Compression {
+        match self {
+            CompressionChoice::Gz => Compression::Gz,
+            CompressionChoice::Xz => Compression::Xz,
+            CompressionChoice::Zst => Compression::Zst,
+            CompressionChoice::Bz2 => Compression::Bz2,
+            CompressionChoice::Not => Compression::Not,
+            CompressionChoice::Inherit => source.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<Compression> for CompressionChoice {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::Gz => CompressionChoice::Gz,
+            Compression::Xz => CompressionChoice::Xz,
+            Compression::Zst => CompressionChoice::Zst,
+            Compression::Bz2 => CompressionChoice::Bz2,
+            Compression::Not => CompressionChoice::Not,
+        }
+    }
+}
+
+/// Which file the generated cargo config is written to.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConfigFormat {
+    /// `.cargo/config`, the only name cargo before 1.39 reads.
+    Legacy,
+    /// `.cargo/config.toml`, preferred by current cargo.
+    #[default]
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Path of the cargo config relative to the project root.
+    pub fn path(self) -> &'static str {
+        match self {
+            ConfigFormat::Legacy => ".cargo/config",
+            ConfigFormat::Toml => ".cargo/config.toml",
+        }
+    }
+}
+
+/// Accepts a plain directory name for `vendor-dir-name`.
+fn parse_vendor_dir_name(name: &str) -> Result<String, String> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(std::path::Component::Normal(_)), None) => Ok(name.to_string()),
+        _ => Err(format!(
+            "`{}` is not a plain directory name. Paths are not allowed",
+            name
+        )),
+    }
+}
+
+/// Accepts a relative path without `..` for `prefix`.
+fn parse_prefix(prefix: &str) -> Result<PathBuf, String> {
+    let mut normalized = PathBuf::new();
+    for component in Path::new(prefix).components() {
+        match component {
+            std::path::Component::Normal(part) => normalized.push(part),
+            std::path::Component::CurDir => {}
+            _ => {
+                return Err(format!(
+                    "`{}` is not a relative path below the tarball root",
+                    prefix
+                ))
+            }
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        return Err(format!("`{}` is an empty prefix", prefix));
+    }
+    Ok(normalized)
+}
+
+/// Accepts a hex encoded SHA-256 digest for `src-sha256`, in lower case.
+fn parse_sha256(digest: &str) -> Result<String, String> {
+    if digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(digest.to_ascii_lowercase())
+    } else {
+        Err(format!(
+            "`{}` is not a SHA-256 digest of 64 hex digits",
+            digest
+        ))
+    }
+}
+
+/// Accepts registry index URLs for `registry-mirror`, like cargo's
+/// `registry` key: a git URL or `sparse+` followed by an HTTP(S) URL.
+fn parse_registry_url(url: &str) -> Result<String, String> {
+    let valid = match url.strip_prefix("sparse+") {
+        Some(sparse) => sparse.starts_with("https://") || sparse.starts_with("http://"),
+        None => ["https://", "http://", "ssh://", "git://", "file://"]
+            .iter()
+            .any(|scheme| url.starts_with(scheme)),
+    };
+    if valid {
+        Ok(url.to_string())
+    } else {
+        Err(format!(
+            "`{}` is not a registry index URL, expected e.g. `sparse+https://...` or a git URL",
+            url
+        ))
+    }
+}
+
+/// Accepts glob patterns for `copy-exclude`.
+fn parse_copy_exclude(pattern: &str) -> Result<String, String> {
+    glob::Pattern::new(pattern)
+        .map(|_| pattern.to_string())
+        .map_err(|err| format!("`{}` is not a valid pattern: {}", pattern, err))
+}
+
+/// Accepts advisory IDs of the form `RUSTSEC-YYYY-NNNN`.
+fn parse_rustsec_id(id: &str) -> Result<String, String> {
+    let valid = id
+        .strip_prefix("RUSTSEC-")
+        .and_then(|rest| rest.split_once('-'))
+        .is_some_and(|(year, number)| {
+            year.len() == 4
+                && number.len() == 4
+                && year.chars().all(|c| c.is_ascii_digit())
+                && number.chars().all(|c| c.is_ascii_digit())
+        });
+    if valid {
+        Ok(id.to_string())
+    } else {
+        Err(format!(
+            "`{}` is not a valid advisory ID. Expected the form RUSTSEC-YYYY-NNNN",
+            id
+        ))
+    }
+}
+
+impl Opts {
+    /// Whether the lockfiles must be used as they are, set by `locked` or `frozen`.
+    pub fn is_locked(&self) -> bool {
+        self.locked || self.frozen
+    }
+
+    /// Whether cargo must not access the network, set by `offline` or `frozen`.
+    pub fn is_offline(&self) -> bool {
+        self.offline || self.frozen
+    }
+
+    /// Whether to update the dependencies, which is on unless `update` is
+    /// set to false or `no-update` is given.
+    pub fn should_update(&self) -> bool {
+        self.update && !self.no_update
+    }
+
+    /// The log level used if `RUST_LOG` is not set, from `quiet` and `verbose`.
+    pub fn log_level(&self) -> &'static str {
+        match (self.quiet, self.verbose) {
+            (true, _) => "warn",
+            (false, 0) => "info",
+            (false, 1) => "debug",
+            (false, _) => "trace",
+        }
+    }
+
+    /// Whether log lines get colored, from `color`. `auto` follows whether
+    /// stdout is a terminal supporting colors and honours `NO_COLOR`; an
+    /// explicit `always` or `never` wins over both.
+    pub fn use_color(&self, is_terminal: bool, supports_color: bool) -> bool {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        self.color_for(is_terminal, supports_color, no_color)
+    }
+
+    /// Like `use_color`, with `no_color` telling whether `NO_COLOR` is set.
+    fn color_for(&self, is_terminal: bool, supports_color: bool, no_color: bool) -> bool {
+        match self.color {
+            clap::ColorChoice::Always => true,
+            clap::ColorChoice::Never => false,
+            clap::ColorChoice::Auto => is_terminal && supports_color && !no_color,
+        }
+    }
+
+    /// The compression of the vendor tarball. `inherit` is replaced by the
+    /// compression of the source when vendoring starts, until then it means
+    /// the default.
+    pub fn output_compression(&self) -> Compression {
+        self.compression.resolve(None)
+    }
+
+    /// Replaces `inherit` in `compression` by `source`, the compression of
+    /// the source tarball or `None` for a directory.
+    fn with_inherited_compression(&self, source: Option<Compression>) -> Cow<'_, Opts> {
+        if self.compression != CompressionChoice::Inherit {
+            return Cow::Borrowed(self);
+        }
+        let compression = self.compression.resolve(source);
+        match source {
+            Some(_) => info!(
+                "🗜️ Compressing the vendor tarball with {} like the source",
+                compression
+            ),
+            None => info!(
+                "🗜️ Source is a directory, compressing the vendor tarball with {}",
+                compression
+            ),
+        }
+        Cow::Owned(Opts {
+            compression: compression.into(),
+            ..self.clone()
+        })
+    }
+
+    /// Builds the options from `matches` and checks what clap cannot check
+    /// on its own. Exits with a usage error like clap does.
+    pub fn parse_checked(matches: &clap::ArgMatches) -> Self {
+        Opts::try_parse_checked(matches).unwrap_or_else(|err| err.exit())
+    }
+
+    fn try_parse_checked(matches: &clap::ArgMatches) -> Result<Self, clap::Error> {
+        let opts =
+            Opts::from_arg_matches(matches).map_err(|err| err.format(&mut Opts::command()))?;
+        // `update` defaults to true, so only an explicit value conflicts.
+        let update_given =
+            matches.value_source("update") == Some(clap::parser::ValueSource::CommandLine);
+        if update_given && opts.update && opts.no_update {
+            return Err(Opts::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "`--update true` cannot be combined with `--no-update`",
+            ));
+        }
+        if opts.frozen && opts.should_update() && update_given {
+            return Err(Opts::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "`--frozen true` does not touch Cargo.lock and cannot be combined with `--update true`",
+            ));
+        }
+        // Every listed source would be extracted to the same kept workdir.
+        if opts.src_list.is_some() && opts.keep_workdir.is_some() {
+            return Err(Opts::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "`--keep-workdir` cannot be combined with `--src-list`",
+            ));
+        }
+        Ok(opts)
+    }
 }
 
 impl AsRef<Opts> for Opts {
@@ -86,166 +596,607 @@ pub struct Src {
     #[arg(
         long,
         visible_aliases = ["srctar", "srcdir"],
-        help = "Where to find sources. Source is either a directory or a source tarball AND cannot be both."
+        help = "Where to find sources. Source is either a directory or a source tarball AND cannot be both. Use `-` to read a tarball from stdin."
     )]
     pub src: PathBuf,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "Which source to use if `src` is a glob matching several files. `version` takes the highest version in the file name, `mtime` the newest file and `name` the last one in plain sort order."
+    )]
+    pub src_select: SrcSelect,
+}
+
+/// How to choose among several sources matching the `src` glob.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SrcSelect {
+    /// The highest version, comparing numbers in the names numerically.
+    #[default]
+    Version,
+    /// The most recently modified.
+    Mtime,
+    /// The last in lexicographic order.
+    Name,
 }
 
 impl Src {
     pub fn new(p: &Path) -> Self {
-        Self { src: p.into() }
+        Self {
+            src: p.into(),
+            src_select: SrcSelect::default(),
+        }
     }
 }
 
 pub trait Vendor {
     fn is_supported(&self) -> Result<SupportedFormat, UnsupportedFormat>;
-    fn run_vendor(&self, opts: &Opts) -> Result<(), OBSCargoError>;
+    fn run_vendor(&self, opts: &Opts) -> Result<Vec<VendorOutput>, OBSCargoError>;
 }
 
 pub fn decompress(comp_type: &Compression, outdir: &Path, src: &Path) -> io::Result<()> {
+    utils::decompress::ensure_tar(*comp_type, src)?;
     match comp_type {
-        Compression::Gz => decompress::targz(outdir, src),
-        Compression::Xz => decompress::tarxz(outdir, src),
-        Compression::Zst => decompress::tarzst(outdir, src),
-        Compression::Bz2 => decompress::tarbz2(outdir, src),
-        Compression::Not => decompress::vanilla(outdir, src),
+        Compression::Gz => utils::decompress::targz(outdir, src),
+        Compression::Xz => utils::decompress::tarxz(outdir, src),
+        Compression::Zst => utils::decompress::tarzst(outdir, src),
+        Compression::Bz2 => utils::decompress::tarbz2(outdir, src),
+        Compression::Not => utils::decompress::vanilla(outdir, src),
+    }
+}
+
+/// Copies stdin into a temporary file in `scratch`. The file is removed
+/// when the returned handle is dropped.
+fn buffer_stdin(scratch: &Path) -> Result<tempfile::NamedTempFile, OBSCargoError> {
+    info!("📥 Reading source tarball from stdin");
+    let mut buffer = tempfile::Builder::new()
+        .prefix(VENDOR_PATH_PREFIX)
+        .tempfile_in(scratch)
+        .map_err(|err| {
+            error!(?err, "Failed to create temporary file for stdin");
+            OBSCargoError::new_with_source(
+                OBSCargoErrorKind::VendorError,
+                "failed to create temporary file for the source from stdin".to_string(),
+                err,
+            )
+        })?;
+    let size = io::copy(&mut io::stdin().lock(), &mut buffer).map_err(|err| {
+        error!(?err, "Failed to read source from stdin");
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorError,
+            "failed to read the source from stdin".to_string(),
+            err,
+        )
+    })?;
+    debug!(size, path = ?buffer.path(), "Buffered source from stdin");
+    Ok(buffer)
+}
+
+impl Src {
+    /// Writes the detected compression and top-level layout of the sources
+    /// to `out`. Nothing is extracted.
+    pub fn inspect(&self, out: &mut impl io::Write) -> Result<(), OBSCargoError> {
+        let stdin_buffer = if self.src.as_os_str() == "-" {
+            Some(buffer_stdin(&std::env::temp_dir())?)
+        } else {
+            None
+        };
+        let src = match &stdin_buffer {
+            Some(buffer) => Src::new(buffer.path()),
+            None => self.clone(),
+        };
+        let format = src.is_supported().map_err(|err| {
+            error!(?err, "Sources cannot be determined!");
+            OBSCargoError::new_with_source(OBSCargoErrorKind::VendorError, err.to_string(), err)
+        })?;
+        let (path, compression, entries) = match format {
+            SupportedFormat::Compressed(compression, path) => {
+                let entries =
+                    utils::decompress::top_level_entries(compression, &path).map_err(|err| {
+                        error!(?err, ?path, "Failed to read source tarball");
+                        OBSCargoError::new_with_source(
+                            OBSCargoErrorKind::VendorError,
+                            format!("failed to read source tarball {}: {}", path.display(), err),
+                            err,
+                        )
+                    })?;
+                (path, compression.to_string(), entries)
+            }
+            SupportedFormat::Dir(path) => {
+                let entries = std::fs::read_dir(&path)
+                    .and_then(|dir| dir.collect::<Result<Vec<_>, _>>())
+                    .map_err(|err| {
+                        error!(?err, ?path, "Failed to read source directory");
+                        OBSCargoError::new_with_source(
+                            OBSCargoErrorKind::VendorError,
+                            format!("failed to read source directory {}", path.display()),
+                            err,
+                        )
+                    })?
+                    .into_iter()
+                    .map(|entry| {
+                        (
+                            entry.file_name().to_string_lossy().to_string(),
+                            entry.path().is_dir(),
+                        )
+                    })
+                    .collect();
+                (path, "none (directory)".to_string(), entries)
+            }
+        };
+        let layout = utils::Layout::classify(entries);
+        let source = match &stdin_buffer {
+            Some(_) => "stdin".into(),
+            None => path.display().to_string(),
+        };
+        writeln!(
+            out,
+            "source: {}\ncompression: {}\nlayout: {}",
+            source, compression, layout
+        )?;
+        Ok(())
     }
 }
 
 impl Vendor for Src {
     fn is_supported(&self) -> Result<SupportedFormat, UnsupportedFormat> {
-        if let Ok(actual_src) = utils::process_globs(&self.src) {
-            debug!(?actual_src, "Source got from glob pattern");
-            if actual_src.is_file() {
-                libroast::is_supported_format(&actual_src)
-            } else {
-                Ok(SupportedFormat::Dir(actual_src))
+        match utils::process_globs(&self.src, self.src_select) {
+            Ok(actual_src) => {
+                debug!(?actual_src, "Source got from glob pattern");
+                if actual_src.is_file() {
+                    let inferred = match infer::get_from_path(&actual_src) {
+                        Ok(Some(known)) => {
+                            debug!(?known);
+                            Compression::from_mime(known.mime_type())
+                        }
+                        _ => Err(UnsupportedFormat {
+                            ext: actual_src
+                                .extension()
+                                .map(|ext| ext.to_string_lossy().to_string())
+                                .unwrap_or_else(|| "unknown format".to_string()),
+                        }),
+                    };
+                    inferred
+                        .map(|compression| {
+                            SupportedFormat::Compressed(compression, actual_src.clone())
+                        })
+                        .or_else(|err| {
+                            // infer does not know every valid variant of the formats
+                            // we support, so have a look at the magic bytes ourselves.
+                            match utils::decompress::sniff_compression(&actual_src) {
+                                Ok(Some(compression)) => {
+                                    debug!(?compression, "Format identified by magic bytes");
+                                    Ok(SupportedFormat::Compressed(compression, actual_src.clone()))
+                                }
+                                Ok(None) => Err(err),
+                                Err(io_err) => {
+                                    warn!(?io_err, "⚠️ Unable to read magic bytes of source");
+                                    Err(err)
+                                }
+                            }
+                        })
+                } else {
+                    Ok(SupportedFormat::Dir(actual_src))
+                }
+            }
+            Err(err) => {
+                error!(?err, "Sources cannot be determined!");
+                Err(UnsupportedFormat {
+                    ext: format!("unsupported source {}: {}", &self.src.display(), err),
+                })
             }
-        } else {
-            error!("Sources cannot be determined!");
-            Err(UnsupportedFormat {
-                ext: format!("unsupported source {}", &self.src.display()),
-            })
         }
     }
 
-    fn run_vendor(&self, opts: &Opts) -> Result<(), OBSCargoError> {
-        let tmpdir = match tempfile::Builder::new()
-            .prefix(VENDOR_PATH_PREFIX)
-            .rand_bytes(8)
-            .tempdir()
-        {
-            Ok(t) => t,
-            Err(err) => {
-                error!("{}", err);
-                return Err(OBSCargoError::new(
-                    OBSCargoErrorKind::VendorError,
-                    "failed to create temporary directory for vendor process".to_string(),
-                ));
+    fn run_vendor(&self, opts: &Opts) -> Result<Vec<VendorOutput>, OBSCargoError> {
+        // Fail before spending minutes on vendoring if we could not write the result.
+        utils::check_outdir(opts)?;
+
+        // `temp_dir` honors `TMPDIR`.
+        let scratch = opts.tmpdir.clone().unwrap_or_else(std::env::temp_dir);
+        utils::check_writable_dir(&scratch, "temporary directory")?;
+        let (tmpdir, workdir) = match &opts.keep_workdir {
+            Some(dir) => {
+                let workdir = utils::prepare_kept_workdir(dir)?;
+                info!("🔍 Working in {}, it is kept afterwards", workdir.display());
+                (None, workdir)
+            }
+            None => {
+                let tmpdir = match tempfile::Builder::new()
+                    .prefix(VENDOR_PATH_PREFIX)
+                    .rand_bytes(8)
+                    .tempdir_in(&scratch)
+                {
+                    Ok(t) => t,
+                    Err(err) => {
+                        error!("{}", err);
+                        return Err(OBSCargoError::new(
+                            OBSCargoErrorKind::VendorError,
+                            "failed to create temporary directory for vendor process".to_string(),
+                        ));
+                    }
+                };
+                // From here on `tmpdir` is removed when dropped. That also covers
+                // early returns and panics, `close` below only reports errors on
+                // success.
+                let workdir: PathBuf = tmpdir.path().into();
+                (Some(tmpdir), workdir)
             }
         };
-
-        let workdir: PathBuf = tmpdir.path().into();
         debug!(?workdir, "Created working directory");
 
+        // A tarball on stdin is buffered next to the working directory, not in
+        // it, so it is not mistaken for part of the sources.
+        let stdin_buffer = if self.src.as_os_str() == "-" {
+            Some(buffer_stdin(&scratch)?)
+        } else {
+            None
+        };
+        let src = match &stdin_buffer {
+            Some(buffer) => Src::new(buffer.path()),
+            None => self.clone(),
+        };
+
+        let copy_filter = utils::CopyFilter::new(&opts.copy_exclude).map_err(|err| {
+            error!(?err, "Invalid copy-exclude pattern");
+            OBSCargoError::new_with_source(
+                OBSCargoErrorKind::VendorError,
+                format!("invalid copy-exclude pattern: {}", err),
+                err,
+            )
+        })?;
+
         // Return workdir here?
-        let newworkdir: PathBuf = match self.is_supported() {
-            Ok(format) => {
-                match format {
+        let (newworkdir, source_compression): (PathBuf, Option<Compression>) =
+            match src.is_supported() {
+                Ok(format) => match format {
                     SupportedFormat::Compressed(compression_type, srcpath) => {
-                        match decompress(&compression_type, &workdir, &srcpath) {
-                            Ok(_) => {
-                                let dirs: Vec<Result<std::fs::DirEntry, std::io::Error>> =
-                                    std::fs::read_dir(&workdir)
-                                        .map_err(|err| {
-                                            error!(?err, "Failed to read directory");
-                                            OBSCargoError::new(
-                                                OBSCargoErrorKind::VendorError,
-                                                "failed to read directory".to_string(),
-                                            )
-                                        })?
-                                        .collect();
-                                trace!(?dirs, "List of files and directories of the workdir");
-                                // If length is one, this means that the project has
-                                // a top-level folder
-                                if dirs.len() != 1 {
-                                    debug!(?workdir);
-                                    workdir
-                                } else {
-                                    match dirs.into_iter().last() {
-                                        Some(p) => match p {
-                                            Ok(dir) => {
-                                                if dir.path().is_dir() {
-                                                    debug!("{}", dir.path().display());
-                                                    dir.path()
-                                                } else {
-                                                    error!(?dir, "Tarball was extracted but got a file and not a possible top-level directory.");
-                                                    return Err(OBSCargoError::new(OBSCargoErrorKind::VendorError, "No top-level directory found after tarball was extracted".to_string()));
-                                                }
-                                            }
-                                            Err(err) => {
-                                                error!(?err, "Failed to read directory entry");
-                                                return Err(OBSCargoError::new(
-                                                    OBSCargoErrorKind::VendorError,
-                                                    err.to_string(),
-                                                ));
-                                            }
-                                        },
-                                        None => {
-                                            error!("This should be unreachable here");
-                                            unreachable!();
-                                        }
-                                    }
-                                }
-                            }
-                            Err(err) => {
-                                return Err(OBSCargoError::new(
-                                    OBSCargoErrorKind::VendorError,
-                                    err.to_string(),
-                                ));
-                            }
+                        if let Some(expected) = &opts.src_sha256 {
+                            utils::verify_sha256(&srcpath, expected)?;
                         }
+                        decompress(&compression_type, &workdir, &srcpath)?;
+                        (utils::top_level_dir(&workdir)?, Some(compression_type))
                     }
-                    SupportedFormat::Dir(srcpath) => match utils::copy_dir_all(
-                        &srcpath,
-                        &workdir.join(srcpath.file_name().unwrap_or(srcpath.as_os_str())),
-                    ) {
-                        Ok(_) => workdir.join(srcpath.file_name().unwrap_or(srcpath.as_os_str())),
-                        Err(err) => {
-                            return Err(OBSCargoError::new(
-                                OBSCargoErrorKind::VendorError,
-                                err.to_string(),
-                            ))
-                        }
-                    },
+                    SupportedFormat::Dir(srcpath) if opts.src_sha256.is_some() => {
+                        return Err(OBSCargoError::new(
+                            OBSCargoErrorKind::VendorError,
+                            format!(
+                                "`src-sha256` needs a tarball, but {} is a directory",
+                                srcpath.display()
+                            ),
+                        ));
+                    }
+                    // Vendoring writes `vendor/`, the cargo config and lockfiles into
+                    // the project, so work on a copy instead of the user's directory.
+                    SupportedFormat::Dir(srcpath) => {
+                        let copy = workdir.join(srcpath.file_name().unwrap_or(srcpath.as_os_str()));
+                        utils::copy_dir_all(&srcpath, &copy, &copy_filter)?;
+                        (copy, None)
+                    }
+                },
+                Err(err) => {
+                    error!(?err);
+                    return Err(OBSCargoError::new_with_source(
+                        OBSCargoErrorKind::VendorError,
+                        err.to_string(),
+                        err,
+                    ));
                 }
-            }
-            Err(err) => {
-                error!(?err);
-                return Err(OBSCargoError::new(
-                    OBSCargoErrorKind::VendorError,
-                    err.to_string(),
-                ));
-            }
-        };
+            };
 
         debug!(?newworkdir, "Workdir updated!");
 
-        match utils::process_src(opts, &newworkdir) {
-            Ok(_) => {
-                info!("🥳 ✨ Successfull ran OBS Service Cargo Vendor ✨");
+        let opts = opts.with_inherited_compression(source_compression);
+        let output = match utils::process_src(&opts, &newworkdir) {
+            Ok(output) => {
+                info!(
+                    tarballs = output.iter().filter(|o| o.tarball.is_some()).count(),
+                    "🥳 ✨ Successfull ran OBS Service Cargo Vendor ✨"
+                );
+                output
             }
             Err(err) => {
                 error!(?err);
-                return Err(OBSCargoError::new(
-                    OBSCargoErrorKind::VendorError,
-                    err.to_string(),
-                ));
+                // Keep the kind, it decides the exit code.
+                return Err(err);
             }
         };
         drop(newworkdir);
-        tmpdir
-            .close()
-            .map_err(|err| OBSCargoError::new(OBSCargoErrorKind::VendorError, err.to_string()))
+        match tmpdir {
+            Some(tmpdir) => tmpdir.close()?,
+            None => info!("🔍 Kept working directory {}", workdir.display()),
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses the options after `cargo_vendor --src . --outdir .`.
+    fn try_parse(args: &[&str]) -> Result<Opts, clap::Error> {
+        let required = ["cargo_vendor", "--src", ".", "--outdir", "."];
+        Opts::try_parse_from(required.iter().chain(args))
+    }
+
+    /// Like `try_parse`, but with the checks of `Opts::parse_checked`.
+    fn try_parse_checked(args: &[&str]) -> Result<Opts, clap::Error> {
+        let required = ["cargo_vendor", "--src", ".", "--outdir", "."];
+        let matches = Opts::command().try_get_matches_from(required.iter().chain(args))?;
+        Opts::try_parse_checked(&matches)
+    }
+
+    #[test]
+    fn update_and_no_update() {
+        assert!(try_parse_checked(&[]).unwrap().should_update());
+        assert!(!try_parse_checked(&["--no-update"]).unwrap().should_update());
+        assert!(!try_parse_checked(&["--update", "false"])
+            .unwrap()
+            .should_update());
+        let opts = try_parse_checked(&["--update", "false", "--no-update"]).unwrap();
+        assert!(!opts.should_update());
+        let opts = try_parse_checked(&["--update", "true", "--no-update", "false"]).unwrap();
+        assert!(opts.should_update());
+        for args in [
+            &["--update", "true", "--no-update"][..],
+            &["--update", "true", "--no-update", "true"],
+        ] {
+            let err = try_parse_checked(args).unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+    }
+
+    #[test]
+    fn rustsec_ids() {
+        assert_eq!(
+            parse_rustsec_id("RUSTSEC-2019-0001").unwrap(),
+            "RUSTSEC-2019-0001"
+        );
+        for malformed in [
+            "",
+            "RUSTSEC-2019",
+            "RUSTSEC-19-0001",
+            "RUSTSEC-2019-00001",
+            "rustsec-2019-0001",
+            "RUSTSEC-2019-000a",
+            "CVE-2019-0001",
+            " RUSTSEC-2019-0001",
+        ] {
+            let err = parse_rustsec_id(malformed).unwrap_err();
+            assert!(err.contains("RUSTSEC-YYYY-NNNN"), "{}", err);
+        }
+        let opts = try_parse(&["--i-accept-the-risk", "RUSTSEC-2019-0001"]).unwrap();
+        assert_eq!(opts.i_accept_the_risk, ["RUSTSEC-2019-0001"]);
+        let err = try_parse(&["--i-accept-the-risk", "RUSTSEC-2019-1"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn registry_urls() {
+        for url in [
+            "sparse+https://mirror.example.com/index/",
+            "sparse+http://localhost:8080/index/",
+            "https://github.com/rust-lang/crates.io-index",
+            "ssh://git@example.com/index.git",
+            "git://example.com/index.git",
+            "file:///srv/index",
+        ] {
+            assert_eq!(parse_registry_url(url).unwrap(), url);
+        }
+        for url in [
+            "",
+            "mirror.example.com/index/",
+            "sparse+file:///srv/index",
+            "sparse+mirror.example.com",
+            "ftp://example.com/index",
+        ] {
+            let err = parse_registry_url(url).unwrap_err();
+            assert!(err.contains("registry index URL"), "{}", err);
+        }
+        let opts = try_parse(&["--registry-mirror", "sparse+https://m.example.com/"]).unwrap();
+        assert_eq!(
+            opts.registry_mirror.as_deref(),
+            Some("sparse+https://m.example.com/")
+        );
+        assert!(try_parse(&["--registry-mirror", "m.example.com"]).is_err());
+    }
+
+    #[test]
+    fn inherited_compression() {
+        let opts = try_parse(&["--compression", "inherit"]).unwrap();
+        let inherited = opts.with_inherited_compression(Some(Compression::Xz));
+        assert_eq!(inherited.compression, CompressionChoice::Xz);
+        assert_eq!(
+            crate::vendor::tarball_name(None, inherited.output_compression()),
+            "vendor.tar.xz"
+        );
+        // A directory has no compression to inherit.
+        let inherited = opts.with_inherited_compression(None);
+        assert_eq!(
+            inherited.output_compression().tar_extension(),
+            Compression::default().tar_extension()
+        );
+        // An explicit choice is kept.
+        let opts = try_parse(&["--compression", "gz"]).unwrap();
+        let kept = opts.with_inherited_compression(Some(Compression::Xz));
+        assert!(matches!(kept, Cow::Borrowed(_)));
+        assert_eq!(kept.compression, CompressionChoice::Gz);
+    }
+
+    #[test]
+    fn sha256_digests() {
+        let digest = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert_eq!(parse_sha256(digest).unwrap(), digest);
+        assert_eq!(parse_sha256(&digest.to_uppercase()).unwrap(), digest);
+        for malformed in [
+            "",
+            &digest[1..],
+            &format!("{}0", digest),
+            &format!("{}g", &digest[1..]),
+            &format!("sha256:{}", digest),
+        ] {
+            let err = parse_sha256(malformed).unwrap_err();
+            assert!(err.contains("64 hex digits"), "{}", err);
+        }
+        let opts = try_parse(&["--src-sha256", &digest.to_uppercase()]).unwrap();
+        assert_eq!(opts.src_sha256.as_deref(), Some(digest));
+        assert!(try_parse(&["--src-sha256", "abc"]).is_err());
+    }
+
+    #[test]
+    fn keep_workdir_and_src_list() {
+        let args = ["cargo_vendor", "--outdir", ".", "--src-list", "sources"];
+        let matches = Opts::command()
+            .try_get_matches_from(args.iter().chain(&["--keep-workdir", "work"]))
+            .unwrap();
+        let err = Opts::try_parse_checked(&matches).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        let matches = Opts::command().try_get_matches_from(args).unwrap();
+        let opts = Opts::try_parse_checked(&matches).unwrap();
+        assert_eq!(opts.src_list.as_deref(), Some(Path::new("sources")));
+        assert!(try_parse_checked(&["--keep-workdir", "work"]).is_ok());
+    }
+
+    #[test]
+    fn frozen_and_update() {
+        let opts = try_parse_checked(&["--frozen", "true"]).unwrap();
+        assert!(opts.is_locked() && opts.is_offline());
+        for args in [
+            &["--frozen", "true", "--update", "false"][..],
+            &["--frozen", "true", "--no-update"],
+            &["--frozen", "false", "--update", "true"],
+        ] {
+            assert!(try_parse_checked(args).is_ok(), "{:?}", args);
+        }
+        let err = try_parse_checked(&["--frozen", "true", "--update", "true"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn vendor_dir_names() {
+        assert_eq!(parse_vendor_dir_name("third-party").unwrap(), "third-party");
+        for name in ["", "../x", "a/b", "/vendor", ".", ".."] {
+            let err = parse_vendor_dir_name(name).unwrap_err();
+            assert!(err.contains("not a plain directory name"), "{}", err);
+        }
+        assert_eq!(try_parse(&[]).unwrap().vendor_dir_name, "vendor");
+        assert!(try_parse(&["--vendor-dir-name", "../x"]).is_err());
+    }
+
+    #[test]
+    fn prefixes() {
+        assert_eq!(parse_prefix("foo-1.0").unwrap(), Path::new("foo-1.0"));
+        assert_eq!(parse_prefix("./foo/bar/").unwrap(), Path::new("foo/bar"));
+        for prefix in ["/foo", "../foo", "foo/../../bar", "foo/.."] {
+            let err = parse_prefix(prefix).unwrap_err();
+            assert!(err.contains("not a relative path"), "{}", err);
+        }
+        for prefix in ["", ".", "./"] {
+            let err = parse_prefix(prefix).unwrap_err();
+            assert!(err.contains("empty prefix"), "{}", err);
+        }
+        let opts = try_parse(&["--prefix", "./foo-1.0"]).unwrap();
+        assert_eq!(opts.prefix.as_deref(), Some(Path::new("foo-1.0")));
+        assert!(try_parse(&["--prefix", "/foo"]).is_err());
+    }
+
+    #[test]
+    fn color_choice() {
+        let auto = try_parse(&[]).unwrap();
+        assert!(auto.color_for(true, true, false));
+        assert!(!auto.color_for(true, true, true));
+        assert!(!auto.color_for(false, true, false));
+        assert!(!auto.color_for(true, false, false));
+        let always = try_parse(&["--color", "always"]).unwrap();
+        assert!(always.color_for(false, false, true));
+        let never = try_parse(&["--color", "never"]).unwrap();
+        assert!(!never.color_for(true, true, false));
+    }
+
+    #[test]
+    fn src_and_src_list() {
+        let parse = |args: &[&str]| {
+            let matches = Opts::command()
+                .try_get_matches_from(["cargo_vendor", "--outdir", "."].iter().chain(args))?;
+            Opts::try_parse_checked(&matches)
+        };
+        let opts = parse(&["--src", "app.tar.gz"]).unwrap();
+        assert_eq!(opts.src.src, Path::new("app.tar.gz"));
+        assert_eq!(opts.src_list, None);
+        // The default `src` stands in for the one `src-list` replaces.
+        let opts = parse(&["--src-list", "sources"]).unwrap();
+        assert_eq!(opts.src_list.as_deref(), Some(Path::new("sources")));
+        let err = parse(&["--src", "app.tar.gz", "--src-list", "sources"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        let err = parse(&[]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn inspect_parsed() {
+        // Neither `outdir` nor the `src` of the vendoring options is needed.
+        let matches = Opts::command()
+            .try_get_matches_from(["cargo_vendor", "inspect", "--src", "app.tar.gz"])
+            .unwrap();
+        let Some(("inspect", sub_matches)) = matches.subcommand() else {
+            panic!("no inspect subcommand in {:?}", matches);
+        };
+        let src = Src::from_arg_matches(sub_matches).unwrap();
+        assert_eq!(src.src, Path::new("app.tar.gz"));
+        let err = Opts::command()
+            .try_get_matches_from(["cargo_vendor", "inspect"])
+            .unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn inspected_compression() {
+        let dir = tempfile::tempdir().unwrap();
+        let prjdir = dir.path().join("app");
+        std::fs::create_dir_all(prjdir.join("app-0.1.0")).unwrap();
+        std::fs::write(prjdir.join("app-0.1.0/Cargo.toml"), "").unwrap();
+        let paths = [prjdir.join("app-0.1.0")];
+        let inspect = |src: &Path| {
+            let mut out = Vec::new();
+            Src::new(src).inspect(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        for (compression, name) in [
+            (Compression::Gz, "gz"),
+            (Compression::Xz, "xz"),
+            (Compression::Zst, "zst"),
+            (Compression::Bz2, "bz2"),
+            (Compression::Not, "tar (uncompressed)"),
+        ] {
+            let tarball = dir
+                .path()
+                .join(format!("app.{}", compression.tar_extension()));
+            let options = utils::compress::ArchiveOptions {
+                threads: Some(1),
+                ..Default::default()
+            };
+            match compression {
+                Compression::Gz => utils::compress::targz(&tarball, &prjdir, &paths, options),
+                Compression::Xz => utils::compress::tarxz(&tarball, &prjdir, &paths, options),
+                Compression::Zst => utils::compress::tarzst(&tarball, &prjdir, &paths, options),
+                Compression::Bz2 => utils::compress::tarbz2(&tarball, &prjdir, &paths, options),
+                Compression::Not => utils::compress::vanilla(&tarball, &prjdir, &paths, options),
+            }
+            .unwrap();
+            let out = inspect(&tarball);
+            assert!(
+                out.contains(&format!("\ncompression: {}\n", name)),
+                "{}",
+                out
+            );
+            assert!(
+                out.contains("layout: single top-level directory app-0.1.0"),
+                "{}",
+                out
+            );
+        }
+        let out = inspect(&prjdir);
+        assert!(out.contains("\ncompression: none (directory)\n"), "{}", out);
     }
 }
@@ -20,11 +20,17 @@ WARNING: To avoid cargo install rebuilding the binary in the install stage
 pub const XZ_EXTS: &[&str] = &["xz"];
 pub const ZST_EXTS: &[&str] = &["zstd", "zst"];
 pub const GZ_EXTS: &[&str] = &["gz", "gzip"];
+pub const BZ2_EXTS: &[&str] = &["bz2", "bzip2"];
+pub const TAR_EXTS: &[&str] = &["tar"];
 pub const XZ_MIME: &str = "application/x-xz";
 pub const ZST_MIME: &str = "application/zstd";
 pub const GZ_MIME: &str = "application/gzip";
 pub const BZ2_MIME: &str = "application/x-bzip2";
-pub const SUPPORTED_MIME_TYPES: &[&str] = &[XZ_MIME, ZST_MIME, GZ_MIME, BZ2_MIME];
+pub const TAR_MIME: &str = "application/x-tar";
+// lzip and lzma sources are read like xz, see `utils::decompress`.
+pub const LZIP_MIME: &str = "application/x-lzip";
+pub const SUPPORTED_MIME_TYPES: &[&str] =
+    &[XZ_MIME, ZST_MIME, GZ_MIME, BZ2_MIME, TAR_MIME, LZIP_MIME];
 pub const EXCLUDED_RUSTSECS: &[&str] = &[
     // NOTE: These two are excluded because they are fundamentally
     // silly and can never be fixed.
@@ -35,3 +41,52 @@ pub const EXCLUDED_RUSTSECS: &[&str] = &[
 ];
 
 pub const OPENSUSE_CARGO_AUDIT_DB: &str = "/usr/share/cargo-audit-advisory-db";
+// The RustSec database gets new advisories every week. If the newest one we
+// know about is older than this, the database is most likely not updated anymore.
+// Documented in the README and the help of `advisory-db`, keep them in sync.
+pub const ADVISORY_DB_MAX_AGE_DAYS: i64 = 180;
+// How often long-running steps log how far they got.
+pub const PROGRESS_INTERVAL_SECS: u64 = 5;
+// Lines of cargo's stderr kept in the error when a cargo command fails.
+pub const CARGO_STDERR_TAIL_LINES: usize = 20;
+// The first retry of a cargo command waits this long, every further one twice as long.
+pub const CARGO_RETRY_BASE_DELAY_SECS: u64 = 1;
+// Cargo errors that a retry can fix. Checked against cargo's stderr.
+pub const CARGO_TRANSIENT_ERRORS: &[&str] = &[
+    "spurious network error",
+    "failed to download",
+    "Could not resolve host",
+    "Couldn't resolve host",
+    "Connection reset",
+    "Connection refused",
+    "timed out",
+    "SSL connect error",
+    "TLS handshake",
+    "network failure",
+];
+// Cargo errors that stay the same no matter how often we retry.
+pub const CARGO_PERMANENT_ERRORS: &[&str] = &[
+    "failed to select a version",
+    "no matching package",
+    "failed to parse manifest",
+    "cyclic package dependency",
+    "needs to be updated but --locked was passed",
+    "attempting to make an HTTP request, but --offline was specified",
+];
+// Files a minimized crate keeps at its top level, matched case-insensitively
+// against the start of the name, e.g. LICENSE-MIT or COPYING.txt.
+pub const MINIMIZE_KEEP_PREFIXES: &[&str] = &[
+    "LICENSE",
+    "LICENCE",
+    "COPYING",
+    "COPYRIGHT",
+    "NOTICE",
+    "AUTHORS",
+    "UNLICENSE",
+];
+// Left out when copying a source directory, see `utils::CopyFilter`. Only
+// the top-level `target` is cargo's, a module may be named like that too.
+pub const COPY_EXCLUDE_DEFAULTS: &[&str] = &[".git", ".hg", ".svn", "/target", "*.orig"];
+// Name of the source the cargo config replaces crates.io with if
+// `registry-mirror` is set.
+pub const REGISTRY_MIRROR_SOURCE: &str = "registry-mirror";
@@ -1,5 +1,7 @@
 use std::error::Error;
 use std::fmt::{Debug, Display};
+use std::io;
+use std::sync::Arc;
 
 #[derive(Clone, Copy, Debug)]
 pub enum OBSCargoErrorKind {
@@ -8,9 +10,25 @@ pub enum OBSCargoErrorKind {
     VendorError,
     AuditError,
     LockFileError,
+    MissingManifest,
 }
 
 impl OBSCargoErrorKind {
+    /// The exit code of the service for this kind of failure. These are
+    /// documented in the README, so do not change existing values.
+    pub fn exit_code(self) -> i32 {
+        use OBSCargoErrorKind::*;
+        match self {
+            AuditNeedsAction => 3,
+            // 4 is reserved for patch failures.
+            VendorCompressionFailed => 5,
+            VendorError => 6,
+            AuditError => 7,
+            LockFileError => 8,
+            MissingManifest => 9,
+        }
+    }
+
     pub(crate) fn as_str(self) -> &'static str {
         use OBSCargoErrorKind::*;
         match self {
@@ -19,6 +37,7 @@ impl OBSCargoErrorKind {
             VendorError => "cargo vendor process failed",
             VendorCompressionFailed => "compress vendored dependencies failed",
             LockFileError => "lockfile generation failed",
+            MissingManifest => "cargo manifest not found",
         }
     }
 }
@@ -27,25 +46,173 @@ impl OBSCargoErrorKind {
 pub struct OBSCargoError {
     kind: OBSCargoErrorKind,
     message: String,
+    /// Further lines like cargo's stderr or the failures of single
+    /// sources, kept out of `message` so that `Display` stays one line.
+    details: Vec<String>,
+    // Arc instead of Box so that the error stays Clone.
+    source: Option<Arc<dyn Error + Send + Sync>>,
 }
 
-impl Error for OBSCargoError {}
+impl Error for OBSCargoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source
+            .as_deref()
+            .map(|source| source as &(dyn Error + 'static))
+    }
+}
 
 impl Debug for OBSCargoError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let full_msg = format!("kind: {}\nreason: {}", self.kind.as_str(), self.message);
+        let mut full_msg = format!("kind: {}\nreason: {}", self.kind.as_str(), self.message);
+        for line in &self.details {
+            full_msg.push_str(&format!("\n  {}", line));
+        }
         write!(f, "{}", full_msg)
     }
 }
 
+/// The short form shown to users, e.g. `cargo vendor process failed: <message>`,
+/// always on one line. `Debug` keeps the longer layout with the details for logs.
 impl Display for OBSCargoError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        Debug::fmt(self, f)
+        write!(f, "{}: {}", self.kind.as_str(), self.message)
     }
 }
 
 impl OBSCargoError {
     pub(crate) fn new(kind: OBSCargoErrorKind, message: String) -> OBSCargoError {
-        Self { kind, message }
+        Self {
+            kind,
+            message,
+            details: Vec::new(),
+            source: None,
+        }
+    }
+
+    pub fn kind(&self) -> OBSCargoErrorKind {
+        self.kind
+    }
+
+    /// What went wrong, without the kind in front of it.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Lines to show below the one line summary.
+    pub fn details(&self) -> &[String] {
+        &self.details
+    }
+
+    pub(crate) fn with_details(mut self, details: impl IntoIterator<Item = String>) -> Self {
+        self.details.extend(details);
+        self
+    }
+
+    pub(crate) fn new_with_source(
+        kind: OBSCargoErrorKind,
+        message: String,
+        source: impl Error + Send + Sync + 'static,
+    ) -> OBSCargoError {
+        Self {
+            kind,
+            message,
+            details: Vec::new(),
+            source: Some(Arc::new(source)),
+        }
+    }
+}
+
+/// Plain I/O failures count as a failed vendor process. The I/O error is
+/// kept as the source.
+impl From<io::Error> for OBSCargoError {
+    fn from(err: io::Error) -> Self {
+        Self::new_with_source(OBSCargoErrorKind::VendorError, err.to_string(), err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_KINDS: [(OBSCargoErrorKind, i32); 6] = [
+        (OBSCargoErrorKind::AuditNeedsAction, 3),
+        (OBSCargoErrorKind::VendorCompressionFailed, 5),
+        (OBSCargoErrorKind::VendorError, 6),
+        (OBSCargoErrorKind::AuditError, 7),
+        (OBSCargoErrorKind::LockFileError, 8),
+        (OBSCargoErrorKind::MissingManifest, 9),
+    ];
+
+    #[test]
+    fn exit_codes_are_stable() {
+        for (kind, code) in ALL_KINDS {
+            assert_eq!(kind.exit_code(), code, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn exit_codes_are_documented() {
+        let readme = include_str!("../../README.md");
+        let table = readme
+            .split("## Exit codes")
+            .nth(1)
+            .and_then(|rest| rest.split("\n#").next())
+            .unwrap();
+        for (kind, code) in ALL_KINDS {
+            assert!(
+                table.contains(&format!("\n| {} | ", code)),
+                "exit code {} of {:?} is not in the README",
+                code,
+                kind
+            );
+        }
+    }
+
+    #[test]
+    fn display_is_one_line() {
+        for (kind, _) in ALL_KINDS {
+            let err = OBSCargoError::new(kind, "something broke".to_string())
+                .with_details(["first detail".to_string(), "second detail".to_string()]);
+            assert_eq!(
+                err.to_string(),
+                format!("{}: something broke", kind.as_str())
+            );
+            assert_eq!(err.details(), ["first detail", "second detail"]);
+            assert!(format!("{:?}", err).contains("\n  second detail"));
+        }
+    }
+
+    #[test]
+    fn source_is_wrapped_error() {
+        let err = OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorError,
+            "failed to read lockfile".to_string(),
+            io::Error::new(io::ErrorKind::NotFound, "Cargo.lock is gone"),
+        );
+        let source = err.source().unwrap();
+        assert_eq!(source.to_string(), "Cargo.lock is gone");
+        let io_err = source.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io_err.kind(), io::ErrorKind::NotFound);
+        // Clones share the source.
+        assert!(err.clone().source().unwrap().is::<io::Error>());
+        assert!(
+            OBSCargoError::new(OBSCargoErrorKind::VendorError, String::new())
+                .source()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn io_error_converted() {
+        fn read(path: &std::path::Path) -> Result<String, OBSCargoError> {
+            Ok(std::fs::read_to_string(path)?)
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let err = read(&dir.path().join("missing")).unwrap_err();
+        assert!(matches!(err.kind(), OBSCargoErrorKind::VendorError));
+        assert_eq!(err.kind().exit_code(), 6);
+        let source = err.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(source.kind(), io::ErrorKind::NotFound);
+        assert_eq!(err.message(), source.to_string());
     }
 }
@@ -9,6 +9,20 @@
 pub mod audit;
 pub mod cli;
 pub mod consts;
-mod errors;
+pub mod errors;
 pub mod utils;
 pub mod vendor;
+
+use crate::cli::{Opts, Vendor};
+use crate::errors::OBSCargoError;
+use crate::vendor::VendorOutput;
+
+/// Vendors, audits and archives the sources given in `opts` and returns what
+/// was produced, one output per vendor tree. Nothing is produced with
+/// `dry_run` set. This is what the `cargo_vendor` service runs.
+pub fn vendor(opts: &Opts) -> Result<Vec<VendorOutput>, OBSCargoError> {
+    match &opts.src_list {
+        Some(list) => utils::process_src_list(opts, list),
+        None => opts.src.run_vendor(opts),
+    }
+}
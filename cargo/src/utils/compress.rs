@@ -0,0 +1,860 @@
+// SPDX-License-Identifier: MPL-2.0
+
+// Copyright (C) 2024 To all Contributors of this project listed in CONTRIBUTORS.md
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use libroast::common::{Compression, UnsupportedFormat};
+
+use super::progress::Progress;
+
+use crate::consts::{
+    BZ2_EXTS, BZ2_MIME, GZ_EXTS, GZ_MIME, LZIP_MIME, TAR_EXTS, TAR_MIME, XZ_EXTS, XZ_MIME,
+    ZST_EXTS, ZST_MIME,
+};
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn, Level};
+
+// These are the levels libroast uses, so not passing a level keeps
+// the tarballs identical to what we produced before.
+const GZ_DEFAULT_LEVEL: u32 = 6;
+const XZ_DEFAULT_LEVEL: u32 = 6;
+const ZST_DEFAULT_LEVEL: u32 = 19;
+const BZ2_DEFAULT_LEVEL: u32 = 9;
+
+/// Lookups from names used for the formats to [`Compression`].
+///
+/// `Compression` lives in libroast, so this is a trait rather than `FromStr`.
+/// Bring it into scope to call e.g. `Compression::from_mime(..)`.
+pub trait CompressionExt: Sized {
+    /// Maps a MIME type as reported by `infer` to the compression.
+    fn from_mime(mime: &str) -> Result<Self, UnsupportedFormat>;
+
+    /// Maps a file extension like `tar.zst`, `.gz` or `tar` to the compression.
+    /// The output of `Display` maps back to the same value.
+    fn from_extension(ext: &str) -> Result<Self, UnsupportedFormat>;
+
+    /// The full extension of a tarball with this compression, e.g. `tar.zst`.
+    fn tar_extension(&self) -> &'static str;
+}
+
+impl CompressionExt for Compression {
+    fn from_mime(mime: &str) -> Result<Self, UnsupportedFormat> {
+        match mime {
+            GZ_MIME => Ok(Compression::Gz),
+            // libroast has no variant for lzip. It uses LZMA like xz, and
+            // reading `Compression::Xz` sources handles both.
+            XZ_MIME | LZIP_MIME => Ok(Compression::Xz),
+            ZST_MIME => Ok(Compression::Zst),
+            BZ2_MIME => Ok(Compression::Bz2),
+            TAR_MIME => Ok(Compression::Not),
+            _ => Err(UnsupportedFormat {
+                ext: mime.to_string(),
+            }),
+        }
+    }
+
+    fn from_extension(ext: &str) -> Result<Self, UnsupportedFormat> {
+        let normalized = ext.trim().trim_start_matches('.').to_ascii_lowercase();
+        // Display of `Compression::Not` is `tar (uncompressed)`.
+        let normalized = normalized.trim_end_matches(" (uncompressed)");
+        let compressor = normalized.strip_prefix("tar.").unwrap_or(normalized);
+        if GZ_EXTS.contains(&compressor) {
+            Ok(Compression::Gz)
+        } else if XZ_EXTS.contains(&compressor) {
+            Ok(Compression::Xz)
+        } else if ZST_EXTS.contains(&compressor) {
+            Ok(Compression::Zst)
+        } else if BZ2_EXTS.contains(&compressor) {
+            Ok(Compression::Bz2)
+        } else if TAR_EXTS.contains(&compressor) {
+            Ok(Compression::Not)
+        } else {
+            Err(UnsupportedFormat {
+                ext: ext.to_string(),
+            })
+        }
+    }
+
+    fn tar_extension(&self) -> &'static str {
+        match self {
+            Compression::Gz => "tar.gz",
+            Compression::Xz => "tar.xz",
+            Compression::Zst => "tar.zst",
+            Compression::Bz2 => "tar.bz2",
+            Compression::Not => "tar",
+        }
+    }
+}
+
+/// Returns the range of levels the encoder for `compression` accepts, or `None`
+/// if the format has no notion of a compression level.
+pub fn level_range(compression: &Compression) -> Option<RangeInclusive<u32>> {
+    match compression {
+        Compression::Gz => Some(0..=9),
+        Compression::Xz => Some(0..=9),
+        Compression::Zst => Some(1..=22),
+        Compression::Bz2 => Some(1..=9),
+        Compression::Not => None,
+    }
+}
+
+pub fn validate_level(compression: &Compression, level: Option<u32>) -> io::Result<()> {
+    let Some(level) = level else {
+        return Ok(());
+    };
+    match level_range(compression) {
+        Some(range) if range.contains(&level) => Ok(()),
+        Some(range) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "compression level {} is out of range for {}. Valid levels are {} to {}",
+                level,
+                compression,
+                range.start(),
+                range.end()
+            ),
+        )),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("compression level cannot be set for {}", compression),
+        )),
+    }
+}
+
+/// Reads `SOURCE_DATE_EPOCH`, which OBS and rpmbuild export so that build
+/// artifacts can carry a stable timestamp.
+pub fn source_date_epoch() -> Option<u64> {
+    let epoch = std::env::var("SOURCE_DATE_EPOCH").ok()?;
+    match epoch.trim().parse::<u64>() {
+        Ok(epoch) => Some(epoch),
+        Err(err) => {
+            warn!(?err, %epoch, "⚠️ Ignoring SOURCE_DATE_EPOCH as it is not a valid timestamp");
+            None
+        }
+    }
+}
+
+fn create_header(metadata: &fs::Metadata, reproducible: bool, mtime: Option<u64>) -> tar::Header {
+    let mut h = tar::Header::new_gnu();
+    if reproducible {
+        h.set_metadata_in_mode(metadata, tar::HeaderMode::Deterministic);
+        h.set_mtime(mtime.unwrap_or(0));
+        h.set_uid(0);
+        h.set_gid(0);
+    } else {
+        h.set_metadata(metadata);
+        if let Some(mtime) = mtime {
+            h.set_mtime(mtime);
+        }
+    }
+    h.set_cksum();
+    h
+}
+
+/// Archive paths of the files added so far, by size and SHA-256 of their
+/// contents. Only kept when deduplicating.
+type SeenFiles = HashMap<(u64, [u8; 32]), PathBuf>;
+
+fn file_digest(path: &Path) -> io::Result<[u8; 32]> {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+fn add_path_to_archive<T: Write>(
+    builder: &mut tar::Builder<T>,
+    additional_path: &Path,
+    target_dir: &Path,
+    prefix: Option<&Path>,
+    reproducible: bool,
+    mtime: Option<u64>,
+    seen: Option<&mut SeenFiles>,
+) -> io::Result<()> {
+    // Never follow symlinks here. A vendored crate may ship links to its
+    // license files or fixtures, and those must stay links in the archive.
+    let metadata = additional_path.symlink_metadata()?;
+    let mut h = create_header(&metadata, reproducible, mtime);
+    // Each path is relative to prjdir. So we can split the
+    // prjdir prefix to get the relative archive path.
+    let subpath = additional_path.strip_prefix(target_dir).map_err(|err| {
+        error!(
+            ?err,
+            "THIS IS A BUG. Unable to proceed. {} is not within {}.",
+            additional_path.to_string_lossy(),
+            target_dir.to_string_lossy()
+        );
+        io::Error::other(additional_path.to_string_lossy())
+    })?;
+    let subpath: PathBuf = match prefix {
+        Some(prefix) => prefix.join(subpath),
+        None => subpath.to_path_buf(),
+    };
+
+    let file_type = metadata.file_type();
+    if file_type.is_symlink() {
+        let target = additional_path.read_link()?;
+        builder.append_link(&mut h, &subpath, target)?;
+    } else if file_type.is_file() {
+        if let Some(seen) = seen.filter(|_| metadata.len() > 0) {
+            let key = (metadata.len(), file_digest(additional_path)?);
+            if let Some(first) = seen.get(&key) {
+                trace!(?subpath, ?first, "Adding duplicate file as hard link");
+                h.set_entry_type(tar::EntryType::Link);
+                h.set_size(0);
+                builder.append_link(&mut h, &subpath, first)?;
+                return Ok(());
+            }
+            seen.insert(key, subpath.clone());
+        }
+        let src = fs::File::open(additional_path).map(io::BufReader::new)?;
+        builder.append_data(&mut h, &subpath, src)?;
+    } else if file_type.is_dir() {
+        // Adding the dir as an empty node
+        builder.append_data(&mut h, &subpath, io::Cursor::new([]))?;
+    } else {
+        error!("Ignoring unexpected special file: {:?}", additional_path);
+    }
+    trace!("Added {} to archive", additional_path.to_string_lossy());
+    Ok(())
+}
+
+/// Appends `archive_files` to the archive with their paths relative to `target_dir`,
+/// stored under `prefix` if one is given. With `dedup`, files with the same
+/// contents as one added before become hard links to it.
+///
+/// Directories are walked and every file is streamed into the builder as it
+/// is reached, so nothing is staged and memory use does not grow with the tree.
+///
+/// Timestamps follow `SOURCE_DATE_EPOCH` when it is set. Otherwise reproducible
+/// archives use a fixed mtime of 0 and non-reproducible ones keep the real mtimes.
+pub fn tar_builder<T: Write>(
+    builder: &mut tar::Builder<T>,
+    target_dir: impl AsRef<Path>,
+    archive_files: &[impl AsRef<Path>],
+    prefix: Option<&Path>,
+    reproducible: bool,
+    dedup: bool,
+) -> io::Result<()> {
+    let mtime = source_date_epoch();
+    debug!(?mtime, reproducible, "Archive entry timestamps");
+    let mut seen: Option<SeenFiles> = dedup.then(HashMap::new);
+    let mut progress = Progress::new("Archiving");
+    for f in archive_files.iter().map(|p| p.as_ref()) {
+        if f.exists() {
+            // Using walkdir for deterministic ordering of the files
+            for entry in walkdir::WalkDir::new(f).sort_by_file_name() {
+                let entry = entry?;
+                let size = match entry.metadata() {
+                    Ok(metadata) if metadata.is_file() => metadata.len(),
+                    _ => 0,
+                };
+                progress.advance(size);
+                add_path_to_archive(
+                    builder,
+                    entry.path(),
+                    target_dir.as_ref(),
+                    prefix,
+                    reproducible,
+                    mtime,
+                    seen.as_mut(),
+                )?;
+            }
+        } else {
+            error!(
+                "THIS IS A BUG. Unable to proceed. {} does not exist.",
+                f.to_string_lossy()
+            );
+            return Err(io::Error::other(f.to_string_lossy()));
+        }
+    }
+
+    progress.finish();
+    builder.finish()
+}
+
+/// Number of entries `tar_builder` adds for `archive_files`.
+pub fn count_archive_entries(archive_files: &[impl AsRef<Path>]) -> io::Result<u64> {
+    let mut count = 0;
+    for f in archive_files.iter().map(|p| p.as_ref()) {
+        for entry in walkdir::WalkDir::new(f) {
+            let file_type = entry?.file_type();
+            if file_type.is_file() || file_type.is_dir() || file_type.is_symlink() {
+                count += 1;
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// Worker threads to hand to the multithreaded encoders, defaulting to
+/// the number of CPUs available to us.
+fn worker_threads(threads: Option<u32>) -> io::Result<u32> {
+    match threads {
+        Some(t) => Ok(t),
+        None => Ok(std::thread::available_parallelism()?.get() as u32),
+    }
+}
+
+/// How `targz` and friends write the archive.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ArchiveOptions<'a> {
+    /// Path the entries are stored under inside the archive.
+    pub prefix: Option<&'a Path>,
+    /// Compression level, `None` for the default of the format.
+    pub level: Option<u32>,
+    /// Worker threads for zst and xz, `None` for one per CPU.
+    pub threads: Option<u32>,
+    /// Sort entries and use fixed timestamps and ownership.
+    pub reproducible: bool,
+    /// Fail once the written archive grows beyond this many bytes.
+    pub max_size: Option<u64>,
+    /// Store files with identical contents once and hard link the others.
+    pub dedup: bool,
+}
+
+/// Writes to a file and fails once more than `max_size` bytes were written,
+/// so an oversized archive is noticed while it is still being written.
+struct SizeLimitedFile {
+    file: fs::File,
+    written: u64,
+    max_size: Option<u64>,
+}
+
+impl Write for SizeLimitedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        match self.max_size {
+            Some(max_size) if self.written > max_size => Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                format!("archive exceeds the maximum size of {} bytes", max_size),
+            )),
+            _ => Ok(n),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn create_outtar(outpath: &Path, max_size: Option<u64>) -> io::Result<SizeLimitedFile> {
+    let file =
+        fs::File::create(outpath).inspect_err(|_| error!(?outpath, "Unable to create outtar"))?;
+    Ok(SizeLimitedFile {
+        file,
+        written: 0,
+        max_size,
+    })
+}
+
+pub fn targz(
+    outpath: impl AsRef<Path>,
+    target_dir: impl AsRef<Path>,
+    archive_files: &[impl AsRef<Path>],
+    options: ArchiveOptions,
+) -> io::Result<()> {
+    use flate2::write::GzEncoder;
+    let outtar = create_outtar(outpath.as_ref(), options.max_size)?;
+    let level = options.level.unwrap_or(GZ_DEFAULT_LEVEL);
+    debug!(level, "Using gz compression level");
+    let encoder = GzEncoder::new(outtar, flate2::Compression::new(level));
+    let mut builder = tar::Builder::new(encoder);
+    tar_builder(
+        &mut builder,
+        target_dir,
+        archive_files,
+        options.prefix,
+        options.reproducible,
+        options.dedup,
+    )?;
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+pub fn tarzst(
+    outpath: impl AsRef<Path>,
+    target_dir: impl AsRef<Path>,
+    archive_files: &[impl AsRef<Path>],
+    options: ArchiveOptions,
+) -> io::Result<()> {
+    use zstd::Encoder;
+    let outtar = create_outtar(outpath.as_ref(), options.max_size)?;
+    let level = options.level.unwrap_or(ZST_DEFAULT_LEVEL);
+    debug!(level, "Using zst compression level");
+    let mut encoder = Encoder::new(outtar, level as i32)?;
+    encoder.include_checksum(true)?;
+    let threads = worker_threads(options.threads)?;
+    debug!(threads, "Using zst worker threads");
+    encoder.multithread(threads)?;
+    let mut builder = tar::Builder::new(encoder);
+    tar_builder(
+        &mut builder,
+        target_dir,
+        archive_files,
+        options.prefix,
+        options.reproducible,
+        options.dedup,
+    )?;
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+pub fn tarxz(
+    outpath: impl AsRef<Path>,
+    target_dir: impl AsRef<Path>,
+    archive_files: &[impl AsRef<Path>],
+    options: ArchiveOptions,
+) -> io::Result<()> {
+    // Crc32 is simpler/faster and often hardware accelerated.
+    use xz2::{
+        stream::{Check::Crc32, MtStreamBuilder},
+        write::XzEncoder,
+    };
+    let outtar = create_outtar(outpath.as_ref(), options.max_size)?;
+    let level = options.level.unwrap_or(XZ_DEFAULT_LEVEL);
+    debug!(level, "Using xz compression level");
+    let threads = worker_threads(options.threads)?;
+    debug!(threads, "Using xz worker threads");
+    let stream = MtStreamBuilder::new()
+        .preset(level)
+        .threads(threads)
+        .check(Crc32)
+        .encoder()?;
+    let encoder = XzEncoder::new_stream(outtar, stream);
+    let mut builder = tar::Builder::new(encoder);
+    tar_builder(
+        &mut builder,
+        target_dir,
+        archive_files,
+        options.prefix,
+        options.reproducible,
+        options.dedup,
+    )?;
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+pub fn tarbz2(
+    outpath: impl AsRef<Path>,
+    target_dir: impl AsRef<Path>,
+    archive_files: &[impl AsRef<Path>],
+    options: ArchiveOptions,
+) -> io::Result<()> {
+    use bzip2::write::BzEncoder;
+    let outtar = create_outtar(outpath.as_ref(), options.max_size)?;
+    let level = options.level.unwrap_or(BZ2_DEFAULT_LEVEL);
+    debug!(level, "Using bz2 compression level");
+    let encoder = BzEncoder::new(outtar, bzip2::Compression::new(level));
+    let mut builder = tar::Builder::new(encoder);
+    tar_builder(
+        &mut builder,
+        target_dir,
+        archive_files,
+        options.prefix,
+        options.reproducible,
+        options.dedup,
+    )?;
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Plain tar. `level` and `threads` of `options` do not apply.
+pub fn vanilla(
+    outpath: impl AsRef<Path>,
+    target_dir: impl AsRef<Path>,
+    archive_files: &[impl AsRef<Path>],
+    options: ArchiveOptions,
+) -> io::Result<()> {
+    let outtar = create_outtar(outpath.as_ref(), options.max_size)?;
+    let mut builder = tar::Builder::new(outtar);
+    tar_builder(
+        &mut builder,
+        target_dir,
+        archive_files,
+        options.prefix,
+        options.reproducible,
+        options.dedup,
+    )?;
+    builder.into_inner()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A vendor directory with nested directories, a relative symlink and
+    /// two crates shipping the same license text.
+    fn sample_tree(root: &Path) -> PathBuf {
+        let vendor = root.join("vendor");
+        for krate in ["a", "b"] {
+            let dir = vendor.join(krate);
+            fs::create_dir_all(dir.join("src")).unwrap();
+            fs::write(dir.join("src/lib.rs"), format!("//! Crate {}\n", krate)).unwrap();
+            fs::write(
+                dir.join("LICENSE"),
+                "Permission is hereby granted\n".repeat(200),
+            )
+            .unwrap();
+        }
+        std::os::unix::fs::symlink("LICENSE", vendor.join("a/COPYING")).unwrap();
+        vendor
+    }
+
+    /// Path, type and mtime of every entry of a tar.zst.
+    fn zst_entries(tarball: &Path) -> Vec<(String, tar::EntryType, u64)> {
+        let decoder = zstd::Decoder::new(fs::File::open(tarball).unwrap()).unwrap();
+        tar::Archive::new(decoder)
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let header = entry.header();
+                (
+                    entry.path().unwrap().display().to_string(),
+                    header.entry_type(),
+                    header.mtime().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn levels_within_bounds() {
+        let bounds = [
+            (Compression::Gz, 0, 9),
+            (Compression::Xz, 0, 9),
+            (Compression::Zst, 1, 22),
+            (Compression::Bz2, 1, 9),
+        ];
+        for (compression, min, max) in bounds {
+            assert!(
+                validate_level(&compression, Some(min)).is_ok(),
+                "{}",
+                compression
+            );
+            assert!(
+                validate_level(&compression, Some(max)).is_ok(),
+                "{}",
+                compression
+            );
+            let err = validate_level(&compression, Some(max + 1)).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(err.to_string().contains("out of range"), "{}", err);
+            if min > 0 {
+                assert!(validate_level(&compression, Some(min - 1)).is_err());
+            }
+        }
+        assert!(validate_level(&Compression::Not, Some(1)).is_err());
+    }
+
+    #[test]
+    fn unset_level_is_valid() {
+        for compression in [
+            Compression::Gz,
+            Compression::Xz,
+            Compression::Zst,
+            Compression::Bz2,
+            Compression::Not,
+        ] {
+            assert!(
+                validate_level(&compression, None).is_ok(),
+                "{}",
+                compression
+            );
+        }
+    }
+
+    #[test]
+    fn unset_level_keeps_libroast_defaults() {
+        // The levels libroast passes to its encoders.
+        assert_eq!(GZ_DEFAULT_LEVEL, flate2::Compression::default().level());
+        assert_eq!(BZ2_DEFAULT_LEVEL, bzip2::Compression::best().level());
+        assert_eq!(ZST_DEFAULT_LEVEL, 19);
+        assert_eq!(XZ_DEFAULT_LEVEL, 6);
+
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("data.txt"), "some data to compress\n".repeat(100)).unwrap();
+        type Compress = fn(&Path, &Path, &[&Path], ArchiveOptions) -> io::Result<()>;
+        let cases: [(&str, Compress, u32); 4] = [
+            ("gz", |o, t, f, opt| targz(o, t, f, opt), GZ_DEFAULT_LEVEL),
+            ("xz", |o, t, f, opt| tarxz(o, t, f, opt), XZ_DEFAULT_LEVEL),
+            (
+                "zst",
+                |o, t, f, opt| tarzst(o, t, f, opt),
+                ZST_DEFAULT_LEVEL,
+            ),
+            (
+                "bz2",
+                |o, t, f, opt| tarbz2(o, t, f, opt),
+                BZ2_DEFAULT_LEVEL,
+            ),
+        ];
+        for (name, compress, default) in cases {
+            let unset = dir.path().join(format!("unset.{}", name));
+            let explicit = dir.path().join(format!("explicit.{}", name));
+            let options = ArchiveOptions {
+                reproducible: true,
+                threads: Some(1),
+                ..Default::default()
+            };
+            compress(&unset, dir.path(), &[&src], options).unwrap();
+            compress(
+                &explicit,
+                dir.path(),
+                &[&src],
+                ArchiveOptions {
+                    level: Some(default),
+                    ..options
+                },
+            )
+            .unwrap();
+            assert_eq!(
+                fs::read(&unset).unwrap(),
+                fs::read(&explicit).unwrap(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn reproducible_archives_are_identical() {
+        let dir = tempfile::tempdir().unwrap();
+        let vendor = sample_tree(dir.path());
+        let options = ArchiveOptions {
+            reproducible: true,
+            ..Default::default()
+        };
+        let first = dir.path().join("first.tar.zst");
+        let second = dir.path().join("second.tar.zst");
+        tarzst(&first, dir.path(), &[&vendor], options).unwrap();
+        // Neither timestamps nor permissions of the tree may leak into it.
+        let lib = vendor.join("a/src/lib.rs");
+        fs::File::options()
+            .write(true)
+            .open(&lib)
+            .unwrap()
+            .set_modified(std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1))
+            .unwrap();
+        tarzst(&second, dir.path(), &[&vendor], options).unwrap();
+        assert_eq!(fs::read(&first).unwrap(), fs::read(&second).unwrap());
+
+        let gz_first = dir.path().join("first.tar.gz");
+        let gz_second = dir.path().join("second.tar.gz");
+        targz(&gz_first, dir.path(), &[&vendor], options).unwrap();
+        targz(&gz_second, dir.path(), &[&vendor], options).unwrap();
+        assert_eq!(fs::read(&gz_first).unwrap(), fs::read(&gz_second).unwrap());
+
+        let entries = zst_entries(&first);
+        let names: Vec<&str> = entries.iter().map(|(name, _, _)| name.as_str()).collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+        if source_date_epoch().is_none() {
+            assert!(entries.iter().all(|(_, _, mtime)| *mtime == 0));
+        }
+    }
+
+    #[test]
+    fn multithreaded_zst_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let vendor = sample_tree(dir.path());
+        // Enough data for the workers to get more than one job each.
+        for i in 0..64 {
+            fs::write(
+                vendor.join(format!("b/src/gen{}.rs", i)),
+                format!("pub const N{}: u32 = {};\n", i, i).repeat(4000),
+            )
+            .unwrap();
+        }
+        let tarball = |threads: u32| {
+            let path = dir.path().join(format!("vendor-{}.tar.zst", threads));
+            let options = ArchiveOptions {
+                threads: Some(threads),
+                level: Some(3),
+                reproducible: true,
+                ..Default::default()
+            };
+            tarzst(&path, dir.path(), &[&vendor], options).unwrap();
+            path
+        };
+        let single = tarball(1);
+        let multi = tarball(4);
+        assert_eq!(zst_entries(&single), zst_entries(&multi));
+
+        let outdir = dir.path().join("out");
+        fs::create_dir_all(&outdir).unwrap();
+        crate::utils::decompress::tarzst(&outdir, &multi).unwrap();
+        for i in [0, 63] {
+            let name = format!("vendor/b/src/gen{}.rs", i);
+            assert_eq!(
+                fs::read(outdir.join(&name)).unwrap(),
+                fs::read(dir.path().join(&name)).unwrap()
+            );
+        }
+    }
+
+    const ALL_COMPRESSIONS: [Compression; 5] = [
+        Compression::Gz,
+        Compression::Xz,
+        Compression::Zst,
+        Compression::Bz2,
+        Compression::Not,
+    ];
+
+    #[test]
+    fn compression_from_mime() {
+        let cases = [
+            ("application/gzip", "tar.gz"),
+            ("application/x-xz", "tar.xz"),
+            ("application/x-lzip", "tar.xz"),
+            ("application/zstd", "tar.zst"),
+            ("application/x-bzip2", "tar.bz2"),
+            ("application/x-tar", "tar"),
+        ];
+        for (mime, extension) in cases {
+            let compression = Compression::from_mime(mime).unwrap();
+            assert_eq!(compression.tar_extension(), extension, "{}", mime);
+        }
+        let err = Compression::from_mime("application/zip").unwrap_err();
+        assert_eq!(err.ext, "application/zip");
+    }
+
+    #[test]
+    fn compression_from_extension() {
+        let cases = [
+            (["gz", ".gz", "tar.gz", "GZIP"], "tar.gz"),
+            (["xz", ".xz", "tar.xz", " XZ "], "tar.xz"),
+            (["zst", ".zstd", "tar.zst", "tar.zstd"], "tar.zst"),
+            (["bz2", ".bzip2", "tar.bz2", "BZ2"], "tar.bz2"),
+            (["tar", ".tar", "TAR", "tar"], "tar"),
+        ];
+        for (extensions, extension) in cases {
+            for ext in extensions {
+                let compression = Compression::from_extension(ext).unwrap();
+                assert_eq!(compression.tar_extension(), extension, "{}", ext);
+            }
+        }
+        for compression in ALL_COMPRESSIONS {
+            let parsed = Compression::from_extension(&compression.to_string()).unwrap();
+            assert_eq!(parsed.tar_extension(), compression.tar_extension());
+            let parsed = Compression::from_extension(compression.tar_extension()).unwrap();
+            assert_eq!(parsed.tar_extension(), compression.tar_extension());
+        }
+        for unknown in ["zip", "tar.rar", "", "lz4"] {
+            let err = Compression::from_extension(unknown).unwrap_err();
+            assert_eq!(err.ext, unknown);
+        }
+    }
+
+    #[test]
+    fn dedup_shrinks_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let vendor = sample_tree(dir.path());
+        // Incompressible, so gz cannot find the copies on its own.
+        let mut state = 0x2545_f491_u32;
+        let noise: Vec<u8> = (0..96 * 1024)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 24) as u8
+            })
+            .collect();
+        for krate in ["a", "b"] {
+            fs::write(vendor.join(krate).join("blob.bin"), &noise).unwrap();
+        }
+        type Compress = fn(&Path, &Path, &[&Path], ArchiveOptions) -> io::Result<()>;
+        let cases: [(&str, Compress); 2] = [
+            ("tar", |o, t, f, opt| vanilla(o, t, f, opt)),
+            ("tar.gz", |o, t, f, opt| targz(o, t, f, opt)),
+        ];
+        for (extension, compress) in cases {
+            let size = |dedup: bool| {
+                let path = dir.path().join(format!("dedup-{}.{}", dedup, extension));
+                let options = ArchiveOptions {
+                    dedup,
+                    reproducible: true,
+                    ..Default::default()
+                };
+                compress(&path, dir.path(), &[&vendor], options).unwrap();
+                fs::metadata(&path).unwrap().len()
+            };
+            let (plain, deduped) = (size(false), size(true));
+            assert!(
+                deduped + 90 * 1024 < plain,
+                "{}: {} with dedup, {} without",
+                extension,
+                deduped,
+                plain
+            );
+        }
+
+        let outdir = dir.path().join("out");
+        crate::utils::decompress::vanilla(&outdir, dir.path().join("dedup-true.tar")).unwrap();
+        let (first, copy) = (
+            outdir.join("vendor/a/blob.bin"),
+            outdir.join("vendor/b/blob.bin"),
+        );
+        for path in [&first, &copy] {
+            assert!(
+                path.symlink_metadata().unwrap().is_file(),
+                "{}",
+                path.display()
+            );
+            assert_eq!(fs::read(path).unwrap(), noise);
+        }
+        assert_eq!(
+            fs::read_to_string(outdir.join("vendor/b/LICENSE")).unwrap(),
+            fs::read_to_string(vendor.join("b/LICENSE")).unwrap()
+        );
+        // Like `patch` does, write a new file and move it over the old one.
+        let patched = outdir.join("vendor/b/blob.bin.orig");
+        fs::write(&patched, "patched").unwrap();
+        fs::rename(&patched, &copy).unwrap();
+        assert_eq!(fs::read(&first).unwrap(), noise);
+    }
+
+    #[test]
+    fn entries_stored_under_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let vendor = sample_tree(dir.path());
+        let tarball = dir.path().join("vendor.tar.zst");
+        let options = ArchiveOptions {
+            prefix: Some(Path::new("foo-1.0")),
+            reproducible: true,
+            ..Default::default()
+        };
+        tarzst(&tarball, dir.path(), &[&vendor], options).unwrap();
+        let paths: Vec<String> = zst_entries(&tarball)
+            .into_iter()
+            .map(|(path, _, _)| path)
+            .collect();
+        assert!(paths.contains(&"foo-1.0/vendor/a/src/lib.rs".to_string()));
+        for path in &paths {
+            assert!(path.starts_with("foo-1.0/vendor"), "{}", path);
+        }
+
+        let outdir = dir.path().join("out");
+        fs::create_dir_all(&outdir).unwrap();
+        crate::utils::decompress::tarzst(&outdir, &tarball).unwrap();
+        assert_eq!(
+            fs::read_link(outdir.join("foo-1.0/vendor/a/COPYING")).unwrap(),
+            Path::new("LICENSE")
+        );
+    }
+}
@@ -0,0 +1,943 @@
+// SPDX-License-Identifier: MPL-2.0
+
+// Copyright (C) 2024 To all Contributors of this project listed in CONTRIBUTORS.md
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+use libroast::common::Compression;
+
+use super::progress::Progress;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn, Level};
+
+const GZ_MAGIC: &[u8] = &[0x1f, 0x8b];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+const ZST_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const BZ2_MAGIC: &[u8] = b"BZh";
+const LZIP_MAGIC: &[u8] = b"LZIP";
+// lzma files have no magic. Nearly every encoder uses these properties
+// (lc=3, lp=0, pb=2), which also are the only ones lzip supports.
+const LZMA_DEFAULT_PROPERTIES: u8 = 0x5d;
+const LZMA_HEADER_SIZE: usize = 13;
+const LZIP_HEADER_SIZE: usize = 6;
+const LZIP_TRAILER_SIZE: usize = 20;
+const TAR_MAGIC: &[u8] = b"ustar";
+const TAR_MAGIC_OFFSET: usize = 257;
+const TAR_BLOCK_SIZE: usize = 512;
+const TAR_CHECKSUM_FIELD: std::ops::Range<usize> = 148..156;
+
+/// Skippable zstd frames have a magic number of 0x184D2A5? in little endian.
+fn is_zst_skippable_frame(header: &[u8]) -> bool {
+    header.len() >= 4 && header[0] & 0xf0 == 0x50 && header[1..4] == [0x2a, 0x4d, 0x18]
+}
+
+/// Reads up to `buf.len()` bytes, stopping early only at end of file.
+fn read_up_to(src: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match src.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+/// Skips any leading skippable zstd frames and checks that a regular zstd
+/// frame follows. `infer` only knows the regular frame magic.
+fn is_zst_with_skippable_frames(file: &mut fs::File) -> io::Result<bool> {
+    let mut pos = 0u64;
+    loop {
+        file.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 8];
+        let n = read_up_to(file, &mut header)?;
+        if n >= 4 && header[..4] == *ZST_MAGIC {
+            return Ok(true);
+        }
+        if n < 8 || !is_zst_skippable_frame(&header) {
+            return Ok(false);
+        }
+        let frame_size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        pos += 8 + u64::from(frame_size);
+    }
+}
+
+/// Whether `header` starts like an lzma file: the usual properties and a
+/// plausible uncompressed size, which is all ones if unknown.
+fn is_lzma_header(header: &[u8]) -> bool {
+    let Some(size) = header.get(5..LZMA_HEADER_SIZE) else {
+        return false;
+    };
+    let size_plausible = size.iter().all(|b| *b == 0xff) || size[6..] == [0, 0];
+    header[0] == LZMA_DEFAULT_PROPERTIES && size_plausible
+}
+
+/// Decodes lzip files. An lzip member is an LZMA stream with the default
+/// properties and an end marker, so liblzma decodes it when it is given an
+/// lzma header in front of it. Members are decoded one after another.
+struct LzipDecoder<R: BufRead> {
+    state: LzipState<R>,
+    members: u64,
+}
+
+enum LzipState<R: BufRead> {
+    /// Before the header of the next member.
+    Header(R),
+    /// Decoding a member.
+    Member(Box<LzipMember<R>>),
+    Done,
+}
+
+struct LzipMember<R: BufRead> {
+    stream: xz2::stream::Stream,
+    /// The lzma header we made up, followed by the rest of the file.
+    input: io::Chain<io::Cursor<Vec<u8>>, R>,
+    /// CRC32 and size of the data decoded so far, to check them against
+    /// the trailer.
+    crc: flate2::Crc,
+    decoded: u64,
+}
+
+impl<R: BufRead> LzipDecoder<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            state: LzipState::Header(inner),
+            members: 0,
+        }
+    }
+
+    fn start_member(&mut self, mut inner: R) -> io::Result<LzipState<R>> {
+        if inner.fill_buf()?.is_empty() && self.members > 0 {
+            return Ok(LzipState::Done);
+        }
+        let mut header = [0u8; LZIP_HEADER_SIZE];
+        inner.read_exact(&mut header)?;
+        if !header.starts_with(LZIP_MAGIC) {
+            if self.members > 0 {
+                // Like lzip, ignore data after the last member.
+                warn!("⚠️ Ignoring trailing data after the last lzip member");
+                return Ok(LzipState::Done);
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an lzip file",
+            ));
+        }
+        if header[4] != 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported lzip version {}", header[4]),
+            ));
+        }
+        // The low 5 bits are the base 2 logarithm of the dictionary size,
+        // the upper 3 bits how many sixteenths of it to subtract.
+        let base = 1u32
+            .checked_shl(u32::from(header[5] & 0x1f))
+            .unwrap_or(u32::MAX);
+        let dict_size = base - (base / 16) * u32::from(header[5] >> 5);
+        let mut lzma_header = vec![LZMA_DEFAULT_PROPERTIES];
+        lzma_header.extend_from_slice(&dict_size.to_le_bytes());
+        // Unknown uncompressed size, the stream ends with the end marker.
+        lzma_header.extend_from_slice(&[0xff; 8]);
+        let stream = xz2::stream::Stream::new_lzma_decoder(u64::MAX)?;
+        self.members += 1;
+        Ok(LzipState::Member(Box::new(LzipMember {
+            stream,
+            input: io::Cursor::new(lzma_header).chain(inner),
+            crc: flate2::Crc::new(),
+            decoded: 0,
+        })))
+    }
+}
+
+/// Reads the trailer of an lzip member and checks the CRC32 of the data,
+/// its size and the size of the member itself against it.
+fn check_lzip_trailer<R: BufRead>(member: LzipMember<R>) -> io::Result<R> {
+    let (_, mut inner) = member.input.into_inner();
+    let mut trailer = [0u8; LZIP_TRAILER_SIZE];
+    inner.read_exact(&mut trailer).map_err(|err| {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            io::Error::new(err.kind(), "lzip member is truncated")
+        } else {
+            err
+        }
+    })?;
+    let le_u64 = |bytes: &[u8]| {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(bytes);
+        u64::from_le_bytes(buf)
+    };
+    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    if crc != member.crc.sum() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "lzip member is corrupt, its CRC does not match",
+        ));
+    }
+    if le_u64(&trailer[4..12]) != member.decoded {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "lzip member has a different size than its trailer says",
+        ));
+    }
+    // The stream was fed our lzma header instead of the lzip one.
+    let member_size = LZIP_HEADER_SIZE as u64 + member.stream.total_in() - LZMA_HEADER_SIZE as u64
+        + LZIP_TRAILER_SIZE as u64;
+    if le_u64(&trailer[12..20]) != member_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "lzip member is longer or shorter than its trailer says",
+        ));
+    }
+    Ok(inner)
+}
+
+impl<R: BufRead> Read for LzipDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match std::mem::replace(&mut self.state, LzipState::Done) {
+                LzipState::Header(inner) => self.state = self.start_member(inner)?,
+                // xz2's readers report data after the end of a stream as
+                // corrupt, so drive the stream ourselves.
+                LzipState::Member(mut member) => {
+                    let data = member.input.fill_buf()?;
+                    let eof = data.is_empty();
+                    let stream = &mut member.stream;
+                    let (before_in, before_out) = (stream.total_in(), stream.total_out());
+                    let status = stream.process(data, buf, xz2::stream::Action::Run)?;
+                    let consumed = (stream.total_in() - before_in) as usize;
+                    let read = (stream.total_out() - before_out) as usize;
+                    member.input.consume(consumed);
+                    member.crc.update(&buf[..read]);
+                    member.decoded += read as u64;
+                    if status == xz2::stream::Status::StreamEnd {
+                        self.state = LzipState::Header(check_lzip_trailer(*member)?);
+                    } else if read == 0 && eof {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "lzip member is truncated",
+                        ));
+                    } else {
+                        self.state = LzipState::Member(member);
+                    }
+                    if read > 0 {
+                        return Ok(read);
+                    }
+                }
+                LzipState::Done => return Ok(0),
+            }
+        }
+    }
+}
+
+/// Decoder for `Compression::Xz` sources. Besides xz, these can be lzip or
+/// lzma files, which libroast has no variants for.
+fn xz_decoder(mut src: io::BufReader<fs::File>) -> io::Result<Box<dyn Read>> {
+    let header = src.fill_buf()?;
+    if header.starts_with(XZ_MAGIC) {
+        Ok(Box::new(xz2::bufread::XzDecoder::new(src)))
+    } else if header.starts_with(LZIP_MAGIC) {
+        Ok(Box::new(LzipDecoder::new(src)))
+    } else {
+        let stream = xz2::stream::Stream::new_lzma_decoder(u64::MAX)?;
+        Ok(Box::new(xz2::bufread::XzDecoder::new_stream(src, stream)))
+    }
+}
+
+/// Identifies the supported formats by their magic bytes. Used as a fallback
+/// for files `infer` cannot classify.
+pub fn sniff_compression(src: &Path) -> io::Result<Option<Compression>> {
+    let mut file = fs::File::open(src)?;
+    let mut header = [0u8; TAR_MAGIC_OFFSET + TAR_MAGIC.len()];
+    let n = read_up_to(&mut file, &mut header)?;
+    let header = &header[..n];
+
+    let compression = if header.starts_with(GZ_MAGIC) {
+        Some(Compression::Gz)
+    } else if header.starts_with(XZ_MAGIC) {
+        Some(Compression::Xz)
+    } else if header.starts_with(ZST_MAGIC) {
+        Some(Compression::Zst)
+    } else if header.starts_with(BZ2_MAGIC) {
+        Some(Compression::Bz2)
+    } else if header.starts_with(LZIP_MAGIC) {
+        // Read like xz, libroast has no variant for lzip.
+        Some(Compression::Xz)
+    } else if header.get(TAR_MAGIC_OFFSET..) == Some(TAR_MAGIC) {
+        Some(Compression::Not)
+    } else if is_zst_skippable_frame(header) && is_zst_with_skippable_frames(&mut file)? {
+        Some(Compression::Zst)
+    } else if is_lzma_header(header) {
+        // Checked last, as lzma has no real magic.
+        Some(Compression::Xz)
+    } else {
+        None
+    };
+    debug!(?src, ?compression, "Sniffed format from magic bytes");
+    Ok(compression)
+}
+
+/// Checks that an archive entry path is relative and has no `..`, then
+/// returns where it will be written to below `outdir`.
+fn checked_destination(outdir: &Path, entry_path: &Path) -> io::Result<PathBuf> {
+    for component in entry_path.components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                error!(
+                    ?entry_path,
+                    "Archive entry escapes the extraction directory"
+                );
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "archive entry {} escapes the extraction directory",
+                        entry_path.display()
+                    ),
+                ));
+            }
+        }
+    }
+
+    let dest = outdir.join(entry_path);
+    // Lexically the path is fine, but an already extracted symlink could
+    // still redirect it. Resolve the deepest ancestor that exists.
+    let mut existing = dest.parent();
+    while let Some(ancestor) = existing {
+        if ancestor.exists() {
+            let resolved = ancestor.canonicalize()?;
+            if !resolved.starts_with(outdir) {
+                error!(
+                    ?entry_path,
+                    ?resolved,
+                    "Archive entry resolves outside of the extraction directory"
+                );
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "archive entry {} resolves to {} which is outside of the extraction directory",
+                        entry_path.display(),
+                        resolved.display()
+                    ),
+                ));
+            }
+            break;
+        }
+        existing = ancestor.parent();
+    }
+    Ok(dest)
+}
+
+/// Checks that a symlink stored at `entry_path` with the given `target`
+/// stays within the extraction root once resolved.
+fn check_link_target(entry_path: &Path, target: &Path) -> io::Result<()> {
+    // Resolve lexically, counting how deep below the root we are. The
+    // link itself lives in the parent directory of the entry. Only normal
+    // components count, `./evil` is at the root like `evil`.
+    let mut depth = 0usize;
+    let mut escapes = false;
+    for component in entry_path.parent().into_iter().flat_map(Path::components) {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                escapes = true;
+                break;
+            }
+        }
+    }
+    if !escapes {
+        for component in target.components() {
+            match component {
+                Component::Normal(_) => depth += 1,
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if depth == 0 {
+                        escapes = true;
+                        break;
+                    }
+                    depth -= 1;
+                }
+                Component::RootDir | Component::Prefix(_) => {
+                    escapes = true;
+                    break;
+                }
+            }
+        }
+    }
+    if escapes {
+        error!(
+            ?entry_path,
+            ?target,
+            "Archive contains a link pointing outside of the extraction directory"
+        );
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "archive entry {} is a link to {} which is outside of the extraction directory",
+                entry_path.display(),
+                target.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Extracts every entry of `archive` below `outdir`, refusing entries and
+/// links that would end up outside of it.
+fn unpack<R: Read>(mut archive: tar::Archive<R>, outdir: &Path) -> io::Result<()> {
+    fs::create_dir_all(outdir)?;
+    let outdir = outdir.canonicalize()?;
+    // Directories are created last so that read-only directory modes do
+    // not stop us from writing their contents. Same as `Archive::unpack`.
+    let mut directories = Vec::new();
+    let mut progress = Progress::new("Extracting");
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        progress.advance(entry.size());
+        let entry_path = entry.path()?.into_owned();
+        checked_destination(&outdir, &entry_path)?;
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            let Some(target) = entry.link_name()? else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "archive entry {} is a link without a target",
+                        entry_path.display()
+                    ),
+                ));
+            };
+            if entry_type.is_symlink() {
+                check_link_target(&entry_path, &target)?;
+            } else {
+                // Hard link targets are relative to the archive root.
+                checked_destination(&outdir, &target)?;
+            }
+        }
+        if entry_type == tar::EntryType::Directory {
+            directories.push(entry);
+        } else {
+            entry.unpack_in(&outdir)?;
+        }
+    }
+    directories.sort_by(|a, b| b.path_bytes().cmp(&a.path_bytes()));
+    for mut dir in directories {
+        dir.unpack_in(&outdir)?;
+    }
+    progress.finish();
+    Ok(())
+}
+
+fn decoder(compression: Compression, src: &Path) -> io::Result<Box<dyn Read>> {
+    let file = io::BufReader::new(fs::File::open(src)?);
+    Ok(match compression {
+        Compression::Gz => Box::new(flate2::bufread::GzDecoder::new(file)),
+        Compression::Xz => xz_decoder(file)?,
+        Compression::Zst => Box::new(zstd::Decoder::with_buffer(file)?),
+        Compression::Bz2 => Box::new(bzip2::bufread::MultiBzDecoder::new(file)),
+        Compression::Not => Box::new(file),
+    })
+}
+
+/// Whether `header` looks like the first block of a tar archive.
+fn is_tar_header(header: &[u8; TAR_BLOCK_SIZE]) -> bool {
+    if header[TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + TAR_MAGIC.len()] == *TAR_MAGIC {
+        return true;
+    }
+    // An empty archive is only its end marker.
+    if header.iter().all(|b| *b == 0) {
+        return true;
+    }
+    // Old v7 archives have no magic, but they do have a header checksum. It
+    // is the sum of all header bytes with the checksum field taken as spaces.
+    let stored = std::str::from_utf8(&header[TAR_CHECKSUM_FIELD])
+        .ok()
+        .map(|field| field.trim_matches([' ', '\0']))
+        .and_then(|field| u32::from_str_radix(field, 8).ok());
+    let sum: u32 = header
+        .iter()
+        .enumerate()
+        .map(|(i, b)| {
+            if TAR_CHECKSUM_FIELD.contains(&i) {
+                u32::from(b' ')
+            } else {
+                u32::from(*b)
+            }
+        })
+        .sum();
+    stored == Some(sum)
+}
+
+/// Fails unless the decompressed `src` starts with a tar header. A plain
+/// `.gz` of a single file has the same magic bytes as a `.tar.gz`, but
+/// would only fail confusingly when extracted.
+pub fn ensure_tar(compression: Compression, src: &Path) -> io::Result<()> {
+    let mut header = [0u8; TAR_BLOCK_SIZE];
+    let n = read_up_to(&mut decoder(compression, src)?, &mut header)?;
+    if n == TAR_BLOCK_SIZE && is_tar_header(&header) {
+        return Ok(());
+    }
+    error!(?src, ?compression, "Source is not a tar archive");
+    let message = match compression {
+        Compression::Not => format!("source {} is not a tar archive", src.display()),
+        _ => format!(
+            "source {} is {}-compressed but not a tar archive",
+            src.display(),
+            compression
+        ),
+    };
+    Err(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+/// Reads the whole archive at `src` and returns how many entries it has.
+/// Reading to the end makes the decoders check their trailing checksums.
+pub fn count_entries(compression: Compression, src: &Path) -> io::Result<u64> {
+    let mut archive = tar::Archive::new(decoder(compression, src)?);
+    let mut count = 0;
+    for entry in archive.entries()? {
+        io::copy(&mut entry?, &mut io::sink())?;
+        count += 1;
+    }
+    io::copy(&mut archive.into_inner(), &mut io::sink())?;
+    Ok(count)
+}
+
+/// Lists the top-level entries of the archive at `src` without extracting
+/// it, as their name and whether they are a directory.
+pub fn top_level_entries(compression: Compression, src: &Path) -> io::Result<Vec<(String, bool)>> {
+    ensure_tar(compression, src)?;
+    let mut archive = tar::Archive::new(decoder(compression, src)?);
+    let mut entries: BTreeMap<String, bool> = BTreeMap::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let path = entry.path()?;
+        let mut components = path
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)));
+        let Some(first) = components.next() else {
+            continue;
+        };
+        let is_dir =
+            components.next().is_some() || entry.header().entry_type() == tar::EntryType::Directory;
+        *entries
+            .entry(first.as_os_str().to_string_lossy().to_string())
+            .or_default() |= is_dir;
+    }
+    Ok(entries.into_iter().collect())
+}
+
+pub fn targz(outdir: impl AsRef<Path>, srcpath: impl AsRef<Path>) -> io::Result<()> {
+    use flate2::bufread::GzDecoder;
+    let src = io::BufReader::new(fs::File::open(srcpath.as_ref())?);
+    let dec = GzDecoder::new(src);
+    unpack(tar::Archive::new(dec), outdir.as_ref())?;
+    debug!(
+        "Successfully decompressed and extracted tape gz-compressed archive from {} to {}",
+        srcpath.as_ref().to_string_lossy(),
+        outdir.as_ref().to_string_lossy(),
+    );
+    Ok(())
+}
+
+pub fn tarzst(outdir: impl AsRef<Path>, srcpath: impl AsRef<Path>) -> io::Result<()> {
+    use zstd::Decoder;
+    let src = io::BufReader::new(fs::File::open(srcpath.as_ref())?);
+    let dec = Decoder::with_buffer(src)?;
+    unpack(tar::Archive::new(dec), outdir.as_ref())?;
+    debug!(
+        "Successfully decompressed and extracted tape zstd-compressed archive from {} to {}",
+        srcpath.as_ref().to_string_lossy(),
+        outdir.as_ref().to_string_lossy(),
+    );
+    Ok(())
+}
+
+/// Also extracts lzip and lzma compressed tarballs.
+pub fn tarxz(outdir: impl AsRef<Path>, srcpath: impl AsRef<Path>) -> io::Result<()> {
+    let src = io::BufReader::new(fs::File::open(srcpath.as_ref())?);
+    let dec = xz_decoder(src)?;
+    unpack(tar::Archive::new(dec), outdir.as_ref())?;
+    debug!(
+        "Successfully decompressed and extracted tape xz-compressed archive from {} to {}",
+        srcpath.as_ref().to_string_lossy(),
+        outdir.as_ref().to_string_lossy(),
+    );
+    Ok(())
+}
+
+pub fn tarbz2(outdir: impl AsRef<Path>, srcpath: impl AsRef<Path>) -> io::Result<()> {
+    use bzip2::bufread::MultiBzDecoder;
+    let src = io::BufReader::new(fs::File::open(srcpath.as_ref())?);
+    let dec = MultiBzDecoder::new(src);
+    unpack(tar::Archive::new(dec), outdir.as_ref())?;
+    debug!(
+        "Successfully decompressed and extracted tape bz2-compressed archive from {} to {}",
+        srcpath.as_ref().to_string_lossy(),
+        outdir.as_ref().to_string_lossy(),
+    );
+    Ok(())
+}
+
+pub fn vanilla(outdir: impl AsRef<Path>, srcpath: impl AsRef<Path>) -> io::Result<()> {
+    let src = io::BufReader::new(fs::File::open(srcpath.as_ref())?);
+    unpack(tar::Archive::new(src), outdir.as_ref())?;
+    debug!(
+        "Successfully extracted tape archive from {} to {}",
+        srcpath.as_ref().to_string_lossy(),
+        outdir.as_ref().to_string_lossy(),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a tar in memory. The names are written into the header as is,
+    /// since `tar::Builder` refuses paths with `..` or a leading `/`.
+    fn crafted_tar(entries: &[(&str, tar::EntryType, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, entry_type, contents_or_target) in entries {
+            let mut header = tar::Header::new_old();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_mode(0o644);
+            let data = if entry_type.is_symlink() || entry_type.is_hard_link() {
+                header.as_old_mut().linkname[..contents_or_target.len()]
+                    .copy_from_slice(contents_or_target.as_bytes());
+                &[][..]
+            } else {
+                contents_or_target.as_bytes()
+            };
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append(&header, data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    /// A scratch directory with `outdir` three levels below its root, so
+    /// escapes with `..` still land inside the scratch directory.
+    fn scratch() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let outdir = dir.path().join("a/b/out");
+        fs::create_dir_all(&outdir).unwrap();
+        (dir, outdir)
+    }
+
+    /// Files and links anywhere in `root` except below `outdir`.
+    fn outside_entries(root: &Path, outdir: &Path) -> Vec<PathBuf> {
+        walkdir::WalkDir::new(root)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| !entry.file_type().is_dir())
+            .map(|entry| entry.path().to_path_buf())
+            .filter(|path| !path.starts_with(outdir))
+            .collect()
+    }
+
+    fn assert_rejected(entries: &[(&str, tar::EntryType, &str)]) {
+        let (dir, outdir) = scratch();
+        let tar = crafted_tar(entries);
+        let err = unpack(tar::Archive::new(io::Cursor::new(tar)), &outdir).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", err);
+        assert!(err.to_string().contains("extraction directory"), "{}", err);
+        assert_eq!(outside_entries(dir.path(), &outdir), Vec::<PathBuf>::new());
+    }
+
+    #[test]
+    fn parent_dir_entry_rejected() {
+        assert_rejected(&[("../../etc/x", tar::EntryType::Regular, "pwned")]);
+    }
+
+    #[test]
+    fn absolute_entry_rejected() {
+        let (dir, outdir) = scratch();
+        let target = dir.path().join("abs");
+        let name = target.to_string_lossy().to_string();
+        let tar = crafted_tar(&[(&name, tar::EntryType::Regular, "pwned")]);
+        let err = unpack(tar::Archive::new(io::Cursor::new(tar)), &outdir).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", err);
+        assert!(!target.exists());
+        assert_rejected(&[("/abs", tar::EntryType::Regular, "pwned")]);
+    }
+
+    #[test]
+    fn write_through_symlink_rejected() {
+        assert_rejected(&[
+            ("escape", tar::EntryType::Symlink, "../.."),
+            ("escape/x", tar::EntryType::Regular, "pwned"),
+        ]);
+    }
+
+    #[test]
+    fn write_through_existing_symlink_rejected() {
+        let (dir, outdir) = scratch();
+        std::os::unix::fs::symlink(dir.path(), outdir.join("escape")).unwrap();
+        let outdir = outdir.canonicalize().unwrap();
+        let err = checked_destination(&outdir, Path::new("escape/x")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", err);
+        assert!(err
+            .to_string()
+            .contains("outside of the extraction directory"));
+    }
+
+    #[test]
+    fn hard_link_escape_rejected() {
+        assert_rejected(&[("link", tar::EntryType::Link, "../x")]);
+    }
+
+    #[test]
+    fn link_depth_ignores_cur_dir() {
+        assert_rejected(&[("./evil", tar::EntryType::Symlink, "../x")]);
+        assert_rejected(&[
+            ("./a/", tar::EntryType::Directory, ""),
+            ("./a/b", tar::EntryType::Symlink, "../../x"),
+        ]);
+        assert!(check_link_target(Path::new("./evil"), Path::new("../x")).is_err());
+        assert!(check_link_target(Path::new("./a/b"), Path::new("../../x")).is_err());
+        assert!(check_link_target(Path::new("a/../b"), Path::new("x")).is_err());
+        assert!(check_link_target(Path::new("./a/b"), Path::new("../x")).is_ok());
+    }
+
+    #[test]
+    fn link_to_sibling_allowed() {
+        let (dir, outdir) = scratch();
+        let tar = crafted_tar(&[
+            ("sibling", tar::EntryType::Regular, "ok"),
+            ("sub/", tar::EntryType::Directory, ""),
+            ("sub/ok", tar::EntryType::Symlink, "../sibling"),
+        ]);
+        unpack(tar::Archive::new(io::Cursor::new(tar)), &outdir).unwrap();
+        assert_eq!(fs::read_to_string(outdir.join("sub/ok")).unwrap(), "ok");
+        assert_eq!(outside_entries(dir.path(), &outdir), Vec::<PathBuf>::new());
+    }
+
+    #[test]
+    fn plain_entries_extracted() {
+        let (dir, outdir) = scratch();
+        let tar = crafted_tar(&[
+            ("proj/Cargo.toml", tar::EntryType::Regular, "[package]"),
+            ("proj/link", tar::EntryType::Symlink, "Cargo.toml"),
+        ]);
+        unpack(tar::Archive::new(io::Cursor::new(tar)), &outdir).unwrap();
+        assert_eq!(
+            fs::read_to_string(outdir.join("proj/link")).unwrap(),
+            "[package]"
+        );
+        assert_eq!(outside_entries(dir.path(), &outdir), Vec::<PathBuf>::new());
+    }
+
+    /// The fixtures in `tests/fixtures/lzma` all hold the same small project,
+    /// compressed as lzip members (in one or two of them) or as lzma.
+    fn lzma_fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/lzma")
+            .join(name)
+    }
+
+    fn extract_fixture(src: &Path) -> io::Result<String> {
+        assert!(matches!(
+            sniff_compression(src).unwrap(),
+            Some(Compression::Xz)
+        ));
+        let outdir = tempfile::tempdir().unwrap();
+        tarxz(outdir.path(), src)?;
+        assert_eq!(count_entries(Compression::Xz, src)?, 4);
+        Ok(fs::read_to_string(outdir.path().join("project-1.0/Cargo.toml")).unwrap())
+    }
+
+    /// Copies a fixture with the byte at `at` flipped.
+    fn corrupted_fixture(name: &str, at: usize) -> (tempfile::TempDir, PathBuf) {
+        let mut data = fs::read(lzma_fixture(name)).unwrap();
+        data[at] ^= 0x01;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        fs::write(&path, data).unwrap();
+        (dir, path)
+    }
+
+    fn assert_extracted(name: &str) {
+        let manifest = extract_fixture(&lzma_fixture(name)).unwrap();
+        assert!(manifest.contains("name = \"project\""), "{}", manifest);
+    }
+
+    fn assert_corrupt(src: &Path, message: &str) {
+        let err = extract_fixture(src).unwrap_err();
+        assert!(
+            err.to_string().contains(message),
+            "{}: {}",
+            src.display(),
+            err
+        );
+    }
+
+    #[test]
+    fn lzip_single_member() {
+        assert_extracted("single.tar.lz");
+    }
+
+    #[test]
+    fn lzip_multi_member() {
+        assert_extracted("multi.tar.lz");
+    }
+
+    #[test]
+    fn lzip_trailing_garbage_ignored() {
+        assert_extracted("trailing-garbage.tar.lz");
+    }
+
+    #[test]
+    fn lzip_truncated() {
+        assert_corrupt(&lzma_fixture("truncated.tar.lz"), "truncated");
+    }
+
+    #[test]
+    fn lzip_trailer_checked() {
+        let len = fs::read(lzma_fixture("single.tar.lz")).unwrap().len();
+        // The trailer is the CRC32, the data size and the member size.
+        let trailer = len - LZIP_TRAILER_SIZE;
+        let (_dir, src) = corrupted_fixture("single.tar.lz", trailer);
+        assert_corrupt(&src, "CRC does not match");
+        let (_dir, src) = corrupted_fixture("single.tar.lz", trailer + 4);
+        assert_corrupt(&src, "different size");
+        let (_dir, src) = corrupted_fixture("single.tar.lz", trailer + 12);
+        assert_corrupt(&src, "longer or shorter");
+    }
+
+    #[test]
+    fn lzip_corrupt_data() {
+        for name in ["single.tar.lz", "multi.tar.lz"] {
+            // In the LZMA data of the first member.
+            let (_dir, src) = corrupted_fixture(name, LZIP_HEADER_SIZE + 40);
+            assert!(extract_fixture(&src).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn lzma_single() {
+        assert_extracted("single.tar.lzma");
+    }
+
+    #[test]
+    fn lzma_truncated() {
+        assert!(extract_fixture(&lzma_fixture("truncated.tar.lzma")).is_err());
+    }
+
+    #[test]
+    fn lzma_trailing_garbage() {
+        assert!(extract_fixture(&lzma_fixture("trailing-garbage.tar.lzma")).is_err());
+    }
+
+    /// Writes `data` to `name` in a fresh directory and sniffs its format.
+    fn sniffed(name: &str, data: &[u8]) -> Option<Compression> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        fs::write(&path, data).unwrap();
+        sniff_compression(&path).unwrap()
+    }
+
+    fn sniffed_name(name: &str, data: &[u8]) -> Option<String> {
+        sniffed(name, data).map(|compression| compression.to_string())
+    }
+
+    #[test]
+    fn compression_sniffed_from_magic_bytes() {
+        use std::io::Write;
+        // `crafted_tar` writes old-style headers, which have no magic.
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_ustar();
+        header.set_size(9);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "proj/Cargo.toml", &b"[package]"[..])
+            .unwrap();
+        let tar = builder.into_inner().unwrap();
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&tar).unwrap();
+        let gz = gz.finish().unwrap();
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 1);
+        xz.write_all(&tar).unwrap();
+        let xz = xz.finish().unwrap();
+        let zst = zstd::encode_all(&tar[..], 1).unwrap();
+        let mut bz2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+        bz2.write_all(&tar).unwrap();
+        let bz2 = bz2.finish().unwrap();
+
+        assert_eq!(sniffed_name("a.tar.gz", &gz), Some("gz".to_string()));
+        assert_eq!(sniffed_name("a.tar.xz", &xz), Some("xz".to_string()));
+        assert_eq!(sniffed_name("a.tar.zst", &zst), Some("zst".to_string()));
+        assert_eq!(sniffed_name("a.tar.bz2", &bz2), Some("bz2".to_string()));
+        assert!(matches!(sniffed("a.tar", &tar), Some(Compression::Not)));
+        // lzip and lzma are read like xz.
+        for fixture in ["single.tar.lz", "single.tar.lzma"] {
+            let data = fs::read(lzma_fixture(fixture)).unwrap();
+            assert_eq!(sniffed_name(fixture, &data), Some("xz".to_string()));
+        }
+
+        // A skippable frame in front of the regular one, as pzstd writes.
+        let mut skippable = vec![0x50, 0x2a, 0x4d, 0x18, 4, 0, 0, 0, 1, 2, 3, 4];
+        skippable.extend_from_slice(&zst);
+        assert_eq!(
+            sniffed_name("a.tar.zst", &skippable),
+            Some("zst".to_string())
+        );
+        // Only skippable frames are not zstd.
+        assert_eq!(sniffed_name("a.zst", &skippable[..12]), None);
+
+        assert_eq!(sniffed_name("a.txt", b"just some text\n"), None);
+        assert_eq!(sniffed_name("empty", b""), None);
+    }
+
+    #[test]
+    fn compressed_non_tar_rejected() {
+        use std::io::Write;
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("notes.tar.gz");
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&b"just some text\n".repeat(100)).unwrap();
+        fs::write(&src, gz.finish().unwrap()).unwrap();
+        let err = ensure_tar(Compression::Gz, &src).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "source {} is gz-compressed but not a tar archive",
+                src.display()
+            )
+        );
+        assert!(top_level_entries(Compression::Gz, &src).is_err());
+
+        let src = dir.path().join("notes.tar");
+        fs::write(&src, b"just some text\n").unwrap();
+        let err = ensure_tar(Compression::Not, &src).unwrap_err();
+        assert!(err.to_string().ends_with("is not a tar archive"), "{}", err);
+
+        let src = dir.path().join("proj.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&src).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(9);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "proj/Cargo.toml", &b"[package]"[..])
+            .unwrap();
+        builder.finish().unwrap();
+        ensure_tar(Compression::Not, &src).unwrap();
+    }
+}
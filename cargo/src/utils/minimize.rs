@@ -0,0 +1,400 @@
+// SPDX-License-Identifier: MPL-2.0
+
+// Copyright (C) 2024 To all Contributors of this project listed in CONTRIBUTORS.md
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::consts::MINIMIZE_KEEP_PREFIXES;
+use crate::utils::progress::human_bytes;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn, Level};
+
+/// Macros and attributes whose string argument names a file the crate reads
+/// at compile time.
+const FILE_REFERENCES: &[&str] = &["include_str!", "include_bytes!", "include!", "#[path"];
+
+/// Target kinds and the directory cargo looks for them in if no `path` is set.
+const TARGET_DIRS: &[(&str, &str)] = &[
+    ("bin", "src/bin"),
+    ("example", "examples"),
+    ("test", "tests"),
+    ("bench", "benches"),
+];
+
+#[derive(Debug, Default)]
+pub struct MinimizeStats {
+    pub crates: u64,
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Removes the files cargo does not need to build the crates in `vendor_dir`
+/// and drops them from each `.cargo-checksum.json`.
+///
+/// A crate keeps `src/`, `Cargo.toml`, its license files, the files its
+/// manifest points to, whatever matches `package.include` and files pulled
+/// in with `include_str!` and friends. Crates with a build script are left
+/// alone, as the build script may read any file.
+pub fn minimize_vendor_dir(vendor_dir: &Path) -> io::Result<MinimizeStats> {
+    let mut stats = MinimizeStats::default();
+    for entry in fs::read_dir(vendor_dir)? {
+        let crate_dir = entry?.path();
+        if !crate_dir.join("Cargo.toml").is_file() {
+            continue;
+        }
+        minimize_crate(&crate_dir, &mut stats)?;
+    }
+    info!(
+        "🗜️ Minimized {} vendored crates: removed {} files, {}",
+        stats.crates,
+        stats.files,
+        human_bytes(stats.bytes)
+    );
+    Ok(stats)
+}
+
+fn minimize_crate(crate_dir: &Path, stats: &mut MinimizeStats) -> io::Result<()> {
+    let data = fs::read_to_string(crate_dir.join("Cargo.toml"))?;
+    let manifest = match toml::from_str::<toml::Value>(&data) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            warn!(
+                ?err,
+                ?crate_dir,
+                "⚠️ Unable to parse vendored manifest, not minimizing it"
+            );
+            return Ok(());
+        }
+    };
+    let package = manifest.get("package");
+    let build = package.and_then(|p| p.get("build"));
+    let has_build_script = match build {
+        Some(toml::Value::Boolean(false)) => false,
+        Some(_) => true,
+        None => crate_dir.join("build.rs").exists(),
+    };
+    if has_build_script {
+        debug!(?crate_dir, "Crate has a build script, not minimizing it");
+        return Ok(());
+    }
+
+    let mut keep: BTreeSet<PathBuf> = ["Cargo.toml", ".cargo-checksum.json"]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    for key in ["readme", "license-file"] {
+        if let Some(path) = package.and_then(|p| p.get(key)).and_then(|v| v.as_str()) {
+            keep.extend(normalize(Path::new(path)));
+        }
+    }
+    if let Some(path) = manifest
+        .get("lib")
+        .and_then(|lib| lib.get("path"))
+        .and_then(|v| v.as_str())
+    {
+        keep.extend(normalize(Path::new(path)));
+    }
+    for (kind, dir) in TARGET_DIRS {
+        let targets = manifest.get(kind).and_then(|v| v.as_array());
+        for target in targets.into_iter().flatten() {
+            if let Some(path) = target.get("path").and_then(|v| v.as_str()) {
+                keep.extend(normalize(Path::new(path)));
+            } else if let Some(name) = target.get("name").and_then(|v| v.as_str()) {
+                keep.insert(Path::new(dir).join(format!("{}.rs", name)));
+                keep.insert(Path::new(dir).join(name).join("main.rs"));
+            }
+        }
+    }
+    let include: Vec<glob::Pattern> = package
+        .and_then(|p| p.get("include"))
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|v| v.as_str())
+        .filter_map(|pattern| glob::Pattern::new(pattern.trim_start_matches('/')).ok())
+        .collect();
+
+    let files: Vec<PathBuf> = walkdir::WalkDir::new(crate_dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            entry
+                .path()
+                .strip_prefix(crate_dir)
+                .ok()
+                .map(Path::to_path_buf)
+        })
+        .collect();
+    keep.extend(
+        files
+            .iter()
+            .filter(|rel| always_kept(rel) || included(rel, &include))
+            .cloned(),
+    );
+
+    // Follow `include_str!` and friends until no new files turn up.
+    let mut to_scan: Vec<PathBuf> = keep
+        .iter()
+        .filter(|rel| rel.extension().is_some_and(|ext| ext == "rs"))
+        .cloned()
+        .collect();
+    while let Some(rel) = to_scan.pop() {
+        let Ok(source) = fs::read_to_string(crate_dir.join(&rel)) else {
+            continue;
+        };
+        for referenced in referenced_files(&rel, &source) {
+            if keep.insert(referenced.clone())
+                && referenced.extension().is_some_and(|ext| ext == "rs")
+            {
+                to_scan.push(referenced);
+            }
+        }
+    }
+
+    let mut removed = BTreeSet::new();
+    for rel in files.iter().filter(|rel| !keep.contains(*rel)) {
+        let path = crate_dir.join(rel);
+        stats.bytes += fs::metadata(&path)?.len();
+        fs::remove_file(&path)?;
+        stats.files += 1;
+        removed.insert(rel.to_string_lossy().replace('\\', "/"));
+    }
+    if removed.is_empty() {
+        return Ok(());
+    }
+    // Directories that are empty now. Walking contents first removes nested
+    // ones before their parents.
+    for entry in walkdir::WalkDir::new(crate_dir)
+        .contents_first(true)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_dir() && entry.path() != crate_dir)
+    {
+        // Fails for directories that still have files, which is fine.
+        let _ = fs::remove_dir(entry.path());
+    }
+    update_checksums(crate_dir, &removed)?;
+    stats.crates += 1;
+    trace!(?crate_dir, removed = removed.len(), "Minimized crate");
+    Ok(())
+}
+
+fn always_kept(rel: &Path) -> bool {
+    let mut components = rel.components();
+    let Some(Component::Normal(first)) = components.next() else {
+        return false;
+    };
+    if first == "src" {
+        return true;
+    }
+    // License files, or a directory of them like REUSE's `LICENSES/`.
+    let first = first.to_string_lossy().to_uppercase();
+    MINIMIZE_KEEP_PREFIXES
+        .iter()
+        .any(|prefix| first.starts_with(prefix))
+}
+
+/// Whether `rel` or one of its parent directories matches `package.include`.
+fn included(rel: &Path, include: &[glob::Pattern]) -> bool {
+    rel.ancestors()
+        .filter(|p| !p.as_os_str().is_empty())
+        .any(|p| include.iter().any(|pattern| pattern.matches_path(p)))
+}
+
+/// Finds the files `source` reads with `include_str!`, `include_bytes!`,
+/// `include!` or `#[path]`, relative to the crate root. `file` is the path of
+/// `source` relative to the crate root.
+fn referenced_files(file: &Path, source: &str) -> Vec<PathBuf> {
+    let base = file.parent().unwrap_or(Path::new(""));
+    let mut found = Vec::new();
+    for marker in FILE_REFERENCES {
+        for (idx, _) in source.match_indices(marker) {
+            let rest = &source[idx + marker.len()..];
+            let end = rest.find([';', ']']).unwrap_or(rest.len());
+            let args = &rest[..end];
+            // Every second piece between quotes is the content of a string
+            // literal.
+            let literals: Vec<&str> = args.split('"').skip(1).step_by(2).collect();
+            let path = if args.contains("CARGO_MANIFEST_DIR") {
+                // concat!(env!("CARGO_MANIFEST_DIR"), "/some/file")
+                let joined: String = literals
+                    .iter()
+                    .filter(|l| **l != "CARGO_MANIFEST_DIR")
+                    .copied()
+                    .collect();
+                normalize(Path::new(joined.trim_start_matches('/')))
+            } else if let Some(literal) = literals.first() {
+                normalize(&base.join(literal))
+            } else {
+                None
+            };
+            found.extend(path);
+        }
+    }
+    found
+}
+
+/// Resolves `.` and `..` without touching the file system. Returns `None`
+/// for paths that leave the crate.
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+/// Drops the removed files from `.cargo-checksum.json`. The hashes of the
+/// remaining files and of the package stay valid, as their contents did not
+/// change.
+fn update_checksums(crate_dir: &Path, removed: &BTreeSet<String>) -> io::Result<()> {
+    let checksum_file = crate_dir.join(".cargo-checksum.json");
+    if !checksum_file.exists() {
+        return Ok(());
+    }
+    let data = fs::read_to_string(&checksum_file)?;
+    let mut checksums: serde_json::Value = serde_json::from_str(&data)?;
+    if let Some(files) = checksums
+        .get_mut("files")
+        .and_then(serde_json::Value::as_object_mut)
+    {
+        files.retain(|name, _| !removed.contains(name));
+    }
+    fs::write(&checksum_file, serde_json::to_string(&checksums)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    fn sha256(path: &Path) -> String {
+        let digest = Sha256::digest(fs::read(path).unwrap());
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Files of the crate relative to its root, without the checksum file.
+    fn crate_files(crate_dir: &Path) -> BTreeSet<String> {
+        walkdir::WalkDir::new(crate_dir)
+            .into_iter()
+            .map(Result::unwrap)
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| {
+                let rel = entry.path().strip_prefix(crate_dir).unwrap();
+                rel.to_string_lossy().to_string()
+            })
+            .filter(|rel| rel != ".cargo-checksum.json")
+            .collect()
+    }
+
+    /// Writes `files` into a vendored crate along with a checksum file like
+    /// `cargo vendor` writes it.
+    fn vendored_crate(vendor_dir: &Path, name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let crate_dir = vendor_dir.join(name);
+        for (rel, contents) in files {
+            let path = crate_dir.join(rel);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        let checksums: serde_json::Map<String, serde_json::Value> = crate_files(&crate_dir)
+            .into_iter()
+            .map(|rel| {
+                let hash = sha256(&crate_dir.join(&rel));
+                (rel, hash.into())
+            })
+            .collect();
+        let checksum = serde_json::json!({ "files": checksums, "package": "0".repeat(64) });
+        fs::write(crate_dir.join(".cargo-checksum.json"), checksum.to_string()).unwrap();
+        crate_dir
+    }
+
+    #[test]
+    fn minimized_crates_keep_what_the_build_needs() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = "[package]\nname = \"plain\"\nversion = \"1.0.0\"\n";
+        let plain = vendored_crate(
+            dir.path(),
+            "plain",
+            &[
+                ("Cargo.toml", manifest),
+                ("src/lib.rs", "mod gen;\npub const WORDS: &str = include_str!(\"../data/words.txt\");\n"),
+                ("src/gen.rs", "pub static TABLE: &[u8] = include_bytes!(concat!(env!(\"CARGO_MANIFEST_DIR\"), \"/data/table.bin\"));\n"),
+                ("data/words.txt", "hello"),
+                ("data/table.bin", "table"),
+                ("data/unused.bin", "unused"),
+                ("LICENSE-MIT", "MIT"),
+                ("LICENSES/Apache-2.0.txt", "Apache"),
+                ("README.md", "readme"),
+                ("tests/integration.rs", "#[test] fn big() {}"),
+                ("benches/bench.rs", ""),
+            ],
+        );
+        let build_script = vendored_crate(
+            dir.path(),
+            "built",
+            &[
+                (
+                    "Cargo.toml",
+                    "[package]\nname = \"built\"\nversion = \"1.0.0\"\n",
+                ),
+                ("build.rs", "fn main() {}"),
+                ("src/lib.rs", ""),
+                ("tests/integration.rs", ""),
+                ("data/anything.txt", "read by build.rs"),
+            ],
+        );
+        let before = crate_files(&build_script);
+
+        let stats = minimize_vendor_dir(dir.path()).unwrap();
+        assert_eq!(stats.crates, 1);
+        assert_eq!(stats.files, 4);
+
+        let kept: Vec<&str> = vec![
+            "Cargo.toml",
+            "LICENSE-MIT",
+            "LICENSES/Apache-2.0.txt",
+            "data/table.bin",
+            "data/words.txt",
+            "src/gen.rs",
+            "src/lib.rs",
+        ];
+        assert_eq!(
+            crate_files(&plain),
+            kept.iter().map(|s| s.to_string()).collect()
+        );
+        assert!(!plain.join("tests").exists());
+        assert!(!plain.join("benches").exists());
+        assert_eq!(crate_files(&build_script), before);
+
+        // The checksums still describe exactly the files on disk.
+        for crate_dir in [&plain, &build_script] {
+            let checksum = fs::read_to_string(crate_dir.join(".cargo-checksum.json")).unwrap();
+            let checksum: serde_json::Value = serde_json::from_str(&checksum).unwrap();
+            let files = checksum["files"].as_object().unwrap();
+            let listed: BTreeSet<String> = files.keys().cloned().collect();
+            assert_eq!(listed, crate_files(crate_dir));
+            for (rel, hash) in files {
+                assert_eq!(hash, &sha256(&crate_dir.join(rel)), "{}", rel);
+            }
+            assert_eq!(checksum["package"], "0".repeat(64));
+        }
+    }
+}
@@ -7,68 +7,931 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 // SPDX-License-Identifier: MPL-2.0
 
+pub mod compress;
+pub mod decompress;
+pub mod minimize;
+pub mod progress;
+
+use std::cmp::Ordering;
 use std::ffi::OsStr;
 use std::fmt::{self, Debug, Display};
 use std::fs;
 use std::io;
+use std::os::unix::process::CommandExt;
 use std::path::Path;
 use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{self, AtomicUsize};
+use std::time::{Duration, Instant};
 
-use crate::cli::Opts;
+use crate::cli::{ManifestMode, Opts, SrcSelect, Vendor};
+use crate::consts::{
+    CARGO_PERMANENT_ERRORS, CARGO_RETRY_BASE_DELAY_SECS, CARGO_STDERR_TAIL_LINES,
+    CARGO_TRANSIENT_ERRORS, COPY_EXCLUDE_DEFAULTS,
+};
 use crate::errors::OBSCargoError;
 use crate::errors::OBSCargoErrorKind;
-use crate::vendor::{self, generate_lockfile, vendor};
+use crate::vendor::{self, generate_lockfile, vendor, VendorOutput};
 
-use crate::audit::{perform_cargo_audit, process_reports};
+use crate::audit::{
+    audit_findings, perform_cargo_audit, process_reports, shared_advisory_db, write_audit_report,
+};
 
 use glob::glob;
 use libroast::common::Compression;
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn, Level};
 
-pub fn copy_dir_all(src: impl AsRef<Path>, dst: &Path) -> Result<(), io::Error> {
+/// Patterns for entries left out when copying a source directory, in the
+/// style of `.gitignore`. A pattern without `/` matches the name of an entry
+/// at any depth. Other patterns match the path relative to the source
+/// directory, a leading `/` only anchors them there.
+#[derive(Debug, Clone)]
+pub struct CopyFilter {
+    names: Vec<glob::Pattern>,
+    paths: Vec<glob::Pattern>,
+}
+
+impl CopyFilter {
+    /// The default patterns plus `extra`.
+    pub fn new(extra: &[String]) -> Result<Self, glob::PatternError> {
+        let mut filter = CopyFilter {
+            names: Vec::new(),
+            paths: Vec::new(),
+        };
+        for pattern in COPY_EXCLUDE_DEFAULTS
+            .iter()
+            .copied()
+            .chain(extra.iter().map(String::as_str))
+        {
+            let pattern = pattern.trim_end_matches('/');
+            if pattern.contains('/') {
+                filter
+                    .paths
+                    .push(glob::Pattern::new(pattern.trim_start_matches('/'))?);
+            } else {
+                filter.names.push(glob::Pattern::new(pattern)?);
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Whether to leave out the entry at `rel`, relative to the source directory.
+    pub fn excludes(&self, rel: &Path) -> bool {
+        let name_matches = rel
+            .file_name()
+            .is_some_and(|name| self.names.iter().any(|p| p.matches_path(Path::new(name))));
+        name_matches || self.paths.iter().any(|p| p.matches_path(rel))
+    }
+}
+
+/// Copies `src` to `dst`, leaving out what `filter` excludes.
+pub fn copy_dir_all(src: impl AsRef<Path>, dst: &Path, filter: &CopyFilter) -> io::Result<()> {
     debug!("Copying sources");
     debug!(?dst);
+    copy_dir_filtered(src.as_ref(), dst, Path::new(""), filter)
+}
+
+fn copy_dir_filtered(src: &Path, dst: &Path, rel: &Path, filter: &CopyFilter) -> io::Result<()> {
     fs::create_dir_all(dst)?;
-    Ok(for entry in fs::read_dir(src)? {
+    for entry in fs::read_dir(src)? {
         let entry = entry?;
         let ty = entry.file_type()?;
+        let entry_rel = rel.join(entry.file_name());
         trace!(?entry);
         trace!(?ty);
+        if filter.excludes(&entry_rel) {
+            debug!(path = ?entry_rel, "Not copying excluded entry");
+            continue;
+        }
         if ty.is_dir() {
             trace!(?ty, "Is directory?");
-            copy_dir_all(entry.path(), &dst.join(entry.file_name()))?;
-
-        // Should we respect symlinks?
-        // } else if ty.is_symlink() {
-        //     debug!("Is symlink");
-        //     let path = fs::read_link(&entry.path())?;
-        //     let path = fs::canonicalize(&path).unwrap();
-        //     debug!(?path);
-        //     let pathfilename = path.file_name().unwrap_or(OsStr::new("."));
-        //     if path.is_dir() {
-        //         copy_dir_all(&path, &dst.join(pathfilename))?;
-        //     } else {
-        //         fs::copy(&path, &mut dst.join(pathfilename))?;
-        //     }
-
-        // Be pedantic or you get symlink error
+            copy_dir_filtered(
+                &entry.path(),
+                &dst.join(entry.file_name()),
+                &entry_rel,
+                filter,
+            )?;
+        } else if ty.is_symlink() {
+            // Recreate the link as is instead of copying what it points to.
+            // Relative links keep working since the layout is the same.
+            trace!(?ty, "Is symlink?");
+            let target = fs::read_link(entry.path())?;
+            std::os::unix::fs::symlink(target, dst.join(entry.file_name()))?;
         } else if ty.is_file() {
             trace!(?ty, "Is file?");
             fs::copy(entry.path(), dst.join(entry.file_name()))?;
         };
+    }
+    Ok(())
+}
+
+/// Entries some archivers add next to the actual sources, like pax headers
+/// extracted as files and `__MACOSX` folders. Every hidden entry counts, not
+/// only AppleDouble `._*` files, so a `.github` next to the project folder
+/// does not hide it either.
+fn is_junk_entry(name: &OsStr) -> bool {
+    let name = name.to_string_lossy();
+    name.starts_with('.') || name == "pax_global_header" || name == "__MACOSX"
+}
+
+/// How the sources are laid out at the top level of a tarball or directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Layout {
+    /// The manifest is right at the root, there is no enclosing folder.
+    Flat,
+    /// A single directory holding the project.
+    TopLevelDir(String),
+    /// A single file and no directory.
+    SingleFile(String),
+    Empty,
+    MultipleDirs(Vec<String>),
+    /// Files next to directories. The root is used as the project root.
+    Mixed,
+}
+
+impl Layout {
+    /// Classifies the top-level entries, given as their name and whether they
+    /// are a directory. Junk entries are ignored.
+    pub fn classify(entries: impl IntoIterator<Item = (String, bool)>) -> Self {
+        let (junk, real): (Vec<_>, Vec<_>) = entries
+            .into_iter()
+            .partition(|(name, _)| is_junk_entry(OsStr::new(name)));
+        if !junk.is_empty() {
+            debug!(
+                ?junk,
+                "Ignoring junk entries for top-level directory detection"
+            );
+        }
+        if real
+            .iter()
+            .any(|(name, is_dir)| name == "Cargo.toml" && !is_dir)
+        {
+            return Layout::Flat;
+        }
+        let (dirs, files): (Vec<_>, Vec<_>) = real.into_iter().partition(|(_, is_dir)| *is_dir);
+        let mut dirs: Vec<String> = dirs.into_iter().map(|(name, _)| name).collect();
+        let mut files: Vec<String> = files.into_iter().map(|(name, _)| name).collect();
+        match (dirs.len(), files.len()) {
+            (1, 0) => Layout::TopLevelDir(dirs.remove(0)),
+            (0, 1) => Layout::SingleFile(files.remove(0)),
+            (0, 0) => Layout::Empty,
+            (_, 0) => {
+                dirs.sort();
+                Layout::MultipleDirs(dirs)
+            }
+            _ => Layout::Mixed,
+        }
+    }
+}
+
+impl Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layout::Flat => write!(f, "flat, the manifest is at the root"),
+            Layout::TopLevelDir(dir) => write!(f, "single top-level directory {}", dir),
+            Layout::SingleFile(file) => write!(f, "a single file {} and no directory", file),
+            Layout::Empty => write!(f, "empty"),
+            Layout::MultipleDirs(dirs) => {
+                write!(f, "multiple top-level directories: {}", dirs.join(", "))
+            }
+            Layout::Mixed => write!(f, "files next to directories, the root is the project root"),
+        }
+    }
+}
+
+/// Finds the project root in a directory a source tarball was extracted to.
+///
+/// That is the single top-level directory if there is one. Junk entries are
+/// ignored when deciding this. Tarballs packed without an enclosing folder
+/// have their manifest right in `workdir`, which is then the project root.
+pub fn top_level_dir(workdir: &Path) -> Result<PathBuf, OBSCargoError> {
+    let entries = fs::read_dir(workdir)
+        .and_then(|dir| dir.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| {
+            error!(?err, "Failed to read directory");
+            OBSCargoError::new_with_source(
+                OBSCargoErrorKind::VendorError,
+                "failed to read directory".to_string(),
+                err,
+            )
+        })?;
+    trace!(?entries, "List of files and directories of the workdir");
+
+    let layout = Layout::classify(entries.iter().map(|entry| {
+        (
+            entry.file_name().to_string_lossy().to_string(),
+            entry.path().is_dir(),
+        )
+    }));
+    match layout {
+        Layout::Flat => {
+            info!("📂 Tarball has no top-level directory. Using its root as the project root.");
+            Ok(workdir.to_path_buf())
+        }
+        // This means that the project has a top-level folder
+        Layout::TopLevelDir(dir) => {
+            let dir = workdir.join(dir);
+            debug!("{}", dir.display());
+            Ok(dir)
+        }
+        Layout::SingleFile(file) => {
+            // Happens when a tarball was packed into another one, e.g. by
+            // compressing a `.tar.gz` once more.
+            if let Ok(Some(compression)) = decompress::sniff_compression(&workdir.join(&file)) {
+                error!(?file, %compression, "Tarball only contains another archive");
+                let kind = match compression {
+                    Compression::Not => "a tarball".to_string(),
+                    _ => format!("{}-compressed", compression),
+                };
+                return Err(OBSCargoError::new(
+                    OBSCargoErrorKind::VendorError,
+                    format!(
+                        "source appears to be a nested archive: it only contains {}, which is {}. Point `src` at the inner archive instead",
+                        file, kind
+                    ),
+                ));
+            }
+            error!(
+                ?file,
+                "Tarball was extracted but got a file and not a possible top-level directory."
+            );
+            Err(OBSCargoError::new(
+                OBSCargoErrorKind::VendorError,
+                "No top-level directory found after tarball was extracted".to_string(),
+            ))
+        }
+        Layout::Empty => {
+            error!("Tarball was extracted but it is empty.");
+            Err(OBSCargoError::new(
+                OBSCargoErrorKind::VendorError,
+                "source tarball does not contain any files".to_string(),
+            ))
+        }
+        Layout::MultipleDirs(names) => {
+            error!(?names, "Tarball has multiple top-level directories");
+            Err(OBSCargoError::new(
+                OBSCargoErrorKind::VendorError,
+                format!(
+                    "source tarball has multiple top-level directories: {}",
+                    names.join(", ")
+                ),
+            ))
+        }
+        // Files next to the directories. Treat the workdir as the project root.
+        Layout::Mixed => {
+            debug!(?workdir);
+            Ok(workdir.to_path_buf())
+        }
+    }
+}
+
+/// Checks that the SHA-256 of the source tarball at `path` is `expected`,
+/// given in lower case hex.
+pub fn verify_sha256(path: &Path, expected: &str) -> Result<(), OBSCargoError> {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    fs::File::open(path)
+        .and_then(|mut file| io::copy(&mut file, &mut hasher))
+        .map_err(|err| {
+            error!(?err, ?path, "Failed to read source for its checksum");
+            OBSCargoError::new_with_source(
+                OBSCargoErrorKind::VendorError,
+                format!("failed to read {} for its checksum", path.display()),
+                err,
+            )
+        })?;
+    let digest: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    if digest != expected {
+        error!(expected, actual = %digest, ?path, "Source checksum mismatch");
+        return Err(OBSCargoError::new(
+            OBSCargoErrorKind::VendorError,
+            format!(
+                "SHA-256 of {} is {}, but {} was expected. The source may have been tampered with",
+                path.display(),
+                digest,
+                expected
+            ),
+        ));
+    }
+    info!("🔏 Source checksum {} verified", digest);
+    Ok(())
+}
+
+/// Checks that `outdir` is a writable directory, creating it first if
+/// `create_outdir` is set.
+pub fn check_outdir(args: &Opts) -> Result<(), OBSCargoError> {
+    let outdir = &args.outdir;
+    if !outdir.exists() {
+        if !args.create_outdir {
+            error!(?outdir, "🛑 Output directory does not exist");
+            return Err(OBSCargoError::new(
+                OBSCargoErrorKind::VendorError,
+                format!(
+                    "output directory {} does not exist. Create it or set `create-outdir` to `true`",
+                    outdir.display()
+                ),
+            ));
+        }
+        if args.dry_run {
+            info!("📝 Would create output directory {}", outdir.display());
+            return Ok(());
+        }
+        info!("📁 Creating output directory {}", outdir.display());
+        fs::create_dir_all(outdir).map_err(|err| {
+            error!(?err, ?outdir, "Failed to create output directory");
+            OBSCargoError::new_with_source(
+                OBSCargoErrorKind::VendorError,
+                format!("failed to create output directory {}", outdir.display()),
+                err,
+            )
+        })?;
+    }
+    check_writable_dir(outdir, "output directory")
+}
+
+/// Fails unless `dir` is a directory we can create files in. `what` names the
+/// directory in the error message.
+pub fn check_writable_dir(dir: &Path, what: &str) -> Result<(), OBSCargoError> {
+    if !dir.exists() {
+        error!(?dir, "🛑 The {} does not exist", what);
+        return Err(OBSCargoError::new(
+            OBSCargoErrorKind::VendorError,
+            format!("{} {} does not exist", what, dir.display()),
+        ));
+    }
+    if !dir.is_dir() {
+        error!(?dir, "🛑 The {} is not a directory", what);
+        return Err(OBSCargoError::new(
+            OBSCargoErrorKind::VendorError,
+            format!("{} {} is not a directory", what, dir.display()),
+        ));
+    }
+    // Permission bits do not tell the whole story, e.g. for read-only
+    // mounts or when running as root. Just try to create a file.
+    tempfile::tempfile_in(dir).map_err(|err| {
+        error!(?err, ?dir, "🛑 The {} is not writable", what);
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorError,
+            format!("{} {} is not writable", what, dir.display()),
+            err,
+        )
+    })?;
+    Ok(())
+}
+
+/// Creates `dir` for `keep-workdir` and returns its absolute path. An existing
+/// directory must be empty, as leftovers of an earlier run would be taken for
+/// part of the sources.
+pub fn prepare_kept_workdir(dir: &Path) -> Result<PathBuf, OBSCargoError> {
+    fs::create_dir_all(dir).map_err(|err| {
+        error!(?err, ?dir, "Failed to create working directory");
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorError,
+            format!("failed to create working directory {}", dir.display()),
+            err,
+        )
+    })?;
+    check_writable_dir(dir, "working directory")?;
+    let not_empty = fs::read_dir(dir)
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(true);
+    if not_empty {
+        error!(?dir, "🛑 The working directory is not empty");
+        return Err(OBSCargoError::new(
+            OBSCargoErrorKind::VendorError,
+            format!("working directory {} is not empty", dir.display()),
+        ));
+    }
+    dir.canonicalize().map_err(|err| {
+        error!(?err, ?dir, "Failed to resolve working directory");
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorError,
+            format!("failed to resolve working directory {}", dir.display()),
+            err,
+        )
     })
 }
 
-pub fn process_src(args: &Opts, prjdir: &Path) -> Result<(), OBSCargoError> {
-    let mut manifest_files: Vec<PathBuf> = if !args.cargotoml.is_empty() {
+/// Finds the manifests of the projects in the subdirectories of `prjdir`,
+/// sorted by directory name. Junk entries are skipped.
+fn find_project_manifests(prjdir: &Path) -> Vec<PathBuf> {
+    let mut manifests: Vec<PathBuf> = fs::read_dir(prjdir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| !is_junk_entry(&entry.file_name()))
+        .map(|entry| entry.path().join("Cargo.toml"))
+        .filter(|manifest| manifest.is_file())
+        .collect();
+    manifests.sort();
+    manifests
+}
+
+/// Returns the manifests unchanged if they all exist. Otherwise fails with
+/// an error that names where we looked and which manifests are there instead.
+fn check_manifests_exist(
+    prjdir: &Path,
+    manifest_files: Vec<PathBuf>,
+) -> Result<Vec<PathBuf>, OBSCargoError> {
+    let missing: Vec<&PathBuf> = manifest_files.iter().filter(|m| !m.is_file()).collect();
+    if missing.is_empty() {
+        return Ok(manifest_files);
+    }
+
+    let found: Vec<String> = walkdir::WalkDir::new(prjdir)
+        .max_depth(3)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name() == "Cargo.toml")
+        .filter_map(|entry| {
+            entry
+                .path()
+                .strip_prefix(prjdir)
+                .ok()
+                .map(|p| p.display().to_string())
+        })
+        .collect();
+
+    let mut message = String::new();
+    for manifest in &missing {
+        error!("🛑 No manifest found at {}", manifest.display());
+        let searched = manifest.parent().unwrap_or(prjdir);
+        let searched = match searched.strip_prefix(prjdir) {
+            Ok(relative) if relative.as_os_str().is_empty() => {
+                "the top-level directory".to_string()
+            }
+            Ok(relative) => relative.display().to_string(),
+            Err(_) => searched.display().to_string(),
+        };
+        message.push_str(&format!("No Cargo.toml found in {}. ", searched));
+    }
+    if found.is_empty() {
+        message.push_str("The sources do not contain any Cargo.toml near the top-level directory.");
+    } else {
+        message.push_str(&format!(
+            "Use the `cargotoml` parameter with a path relative to the top-level directory. Manifests found: {}",
+            found.join(", ")
+        ));
+    }
+    Err(OBSCargoError::new(
+        OBSCargoErrorKind::MissingManifest,
+        message,
+    ))
+}
+
+pub fn process_src(args: &Opts, prjdir: &Path) -> Result<Vec<VendorOutput>, OBSCargoError> {
+    // Catch a bad compression level before spending time on vendoring.
+    compress::validate_level(&args.output_compression(), args.compression_level).map_err(
+        |err| {
+            error!(?err, "Invalid compression level");
+            OBSCargoError::new_with_source(
+                OBSCargoErrorKind::VendorCompressionFailed,
+                err.to_string(),
+                err,
+            )
+        },
+    )?;
+
+    let manifest_files: Vec<PathBuf> = if !args.cargotoml.is_empty() {
         debug!("Using manually specified Cargo.toml files.");
         debug!(?args.cargotoml);
         args.cargotoml.iter().map(|p| prjdir.join(p)).collect()
-    } else {
+    } else if prjdir.join("Cargo.toml").is_file() {
         debug!("Assuming Cargo.toml in root of the projectdir");
         vec![prjdir.join("Cargo.toml")]
+    } else {
+        // Sources that are a directory of several projects side by side.
+        let projects = find_project_manifests(prjdir);
+        if projects.is_empty() {
+            vec![prjdir.join("Cargo.toml")]
+        } else {
+            info!(
+                "📦 No Cargo.toml in the top-level directory, but {} projects next to each other. Vendoring all of them: {}",
+                projects.len(),
+                projects
+                    .iter()
+                    .filter_map(|p| p.parent()?.strip_prefix(prjdir).ok())
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            projects
+        }
+    };
+
+    // Not finding the manifest is by far the most common misconfiguration,
+    // so check before cargo fails with something less helpful.
+    let manifest_files = check_manifests_exist(prjdir, manifest_files)?;
+
+    if args.dry_run {
+        log_plan(args, prjdir, &manifest_files)?;
+        return Ok(Vec::new());
+    }
+
+    match args.manifest_mode {
+        ManifestMode::Separate if manifest_files.len() > 1 => {
+            let jobs = job_count(args, manifest_files.len());
+            info!(
+                "🌳 Vendoring {} manifests separately with {} jobs",
+                manifest_files.len(),
+                jobs
+            );
+            let results = run_parallel(jobs, &manifest_files, |manifest_file| {
+                // Every job only writes below the directory of its own manifest
+                // and to its own tagged tarball, so jobs do not interfere.
+                let treedir = manifest_file.parent().unwrap_or(prjdir);
+                let tag = tree_tag(args.tag.as_deref(), prjdir, treedir);
+                let audit_report = args
+                    .audit_report
+                    .as_deref()
+                    .map(|report| tagged_report_path(report, tag.as_deref()));
+                info!("🌲 Vendoring {}", manifest_file.display());
+                vendor_tree(
+                    args,
+                    prjdir,
+                    treedir,
+                    tag.as_deref(),
+                    audit_report.as_deref(),
+                    vec![manifest_file.clone()],
+                )
+            });
+
+            let names: Vec<String> = manifest_files
+                .iter()
+                .map(|manifest_file| {
+                    let relative = manifest_file.strip_prefix(prjdir).unwrap_or(manifest_file);
+                    relative.display().to_string()
+                })
+                .collect();
+            let results = results
+                .into_iter()
+                .map(|result| result.map(|output| vec![output]));
+            collect_outputs("manifests", &names, results)
+        }
+        _ => Ok(vec![vendor_tree(
+            args,
+            prjdir,
+            prjdir,
+            args.tag.as_deref(),
+            args.audit_report.as_deref(),
+            manifest_files,
+        )?]),
+    }
+}
+
+/// Gathers the outputs of several vendor runs, one per name in `names`.
+/// Every failure is reported in the details instead of only the first one,
+/// which decides the kind of the error and with that the exit code.
+fn collect_outputs(
+    what: &str,
+    names: &[String],
+    results: impl IntoIterator<Item = Result<Vec<VendorOutput>, OBSCargoError>>,
+) -> Result<Vec<VendorOutput>, OBSCargoError> {
+    let mut outputs = Vec::new();
+    let mut failures = Vec::new();
+    for (name, result) in names.iter().zip(results) {
+        match result {
+            Ok(output) => outputs.extend(output),
+            Err(err) => {
+                error!("🛑 Vendoring {} failed: {}", name, err);
+                failures.push((name, err));
+            }
+        }
+    }
+    let Some((_, first_err)) = failures.first() else {
+        return Ok(outputs);
+    };
+    let failed: Vec<&str> = failures.iter().map(|(name, _)| name.as_str()).collect();
+    let mut details = Vec::new();
+    for (name, err) in &failures {
+        details.push(format!("{}: {}", name, err.message()));
+        details.extend(err.details().iter().map(|line| format!("  {}", line)));
+    }
+    Err(OBSCargoError::new(
+        first_err.kind(),
+        format!(
+            "vendoring failed for {} of {} {}: {}",
+            failures.len(),
+            names.len(),
+            what,
+            failed.join(", ")
+        ),
+    )
+    .with_details(details))
+}
+
+/// Number of jobs to run `items` with, `jobs` or one per CPU.
+fn job_count(args: &Opts, items: usize) -> usize {
+    args.jobs
+        .map(|jobs| jobs as usize)
+        .or_else(|| std::thread::available_parallelism().ok().map(usize::from))
+        .unwrap_or(1)
+        .min(items)
+}
+
+/// One line of a `src-list` file.
+#[derive(Debug)]
+struct ListedSrc {
+    src: PathBuf,
+    tag: String,
+}
+
+/// Tag of a listed source without an explicit one: its file name without
+/// the tarball extension, e.g. `foo-1.0` for `foo-1.0.tar.zst`.
+fn listed_src_tag(src: &Path) -> String {
+    let name = src
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| src.display().to_string());
+    let stem = match name.find(".tar") {
+        Some(idx) if idx > 0 => &name[..idx],
+        _ => name.as_str(),
+    };
+    // Globs are fine as sources, but not in file names.
+    stem.replace(['*', '?', '[', ']'], "")
+}
+
+/// Reads a `src-list` file. Every line names a source and optionally the
+/// tag of its vendor tarball, separated by whitespace. Blank lines and lines
+/// starting with `#` are ignored.
+fn read_src_list(list: &Path) -> Result<Vec<ListedSrc>, OBSCargoError> {
+    let data = fs::read_to_string(list).map_err(|err| {
+        error!(?err, ?list, "Unable to read src list");
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorError,
+            format!("unable to read src list {}", list.display()),
+            err,
+        )
+    })?;
+    let mut sources: Vec<ListedSrc> = Vec::new();
+    for (number, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let (Some(src), tag, None) = (fields.next(), fields.next(), fields.next()) else {
+            return Err(OBSCargoError::new(
+                OBSCargoErrorKind::VendorError,
+                format!(
+                    "line {} of src list {} has more than a source and a tag: {}",
+                    number + 1,
+                    list.display(),
+                    line
+                ),
+            ));
+        };
+        let src = PathBuf::from(src);
+        let tag = tag.map_or_else(|| listed_src_tag(&src), str::to_string);
+        if let Some(other) = sources.iter().find(|other| other.tag == tag) {
+            return Err(OBSCargoError::new(
+                OBSCargoErrorKind::VendorError,
+                format!(
+                    "sources {} and {} in src list {} would both get the tag `{}`. Add a tag after one of them",
+                    other.src.display(),
+                    src.display(),
+                    list.display(),
+                    tag
+                ),
+            ));
+        }
+        sources.push(ListedSrc { src, tag });
+    }
+    if sources.is_empty() {
+        return Err(OBSCargoError::new(
+            OBSCargoErrorKind::VendorError,
+            format!("src list {} does not list any sources", list.display()),
+        ));
+    }
+    Ok(sources)
+}
+
+/// Vendors every source in the `src-list` file to its own tagged tarball,
+/// `jobs` of them at the same time.
+pub fn process_src_list(args: &Opts, list: &Path) -> Result<Vec<VendorOutput>, OBSCargoError> {
+    let sources = read_src_list(list)?;
+    let jobs = job_count(args, sources.len());
+    info!(
+        "📜 Vendoring {} sources from {} with {} jobs",
+        sources.len(),
+        list.display(),
+        jobs
+    );
+    let results = run_parallel(jobs, &sources, |listed| {
+        let mut opts = args.clone();
+        opts.src.src = listed.src.clone();
+        let tag = match &args.tag {
+            Some(base) => format!("{}-{}", base, listed.tag),
+            None => listed.tag.clone(),
+        };
+        opts.audit_report = args
+            .audit_report
+            .as_deref()
+            .map(|report| tagged_report_path(report, Some(&tag)));
+        opts.tag = Some(tag);
+        info!("🍿 Vendoring {} as `{}`", listed.src.display(), listed.tag);
+        opts.src.run_vendor(&opts)
+    });
+    let names: Vec<String> = sources
+        .iter()
+        .map(|listed| listed.src.display().to_string())
+        .collect();
+    collect_outputs("sources", &names, results)
+}
+
+/// Logs what a vendor run with these manifests would do.
+fn log_plan(args: &Opts, prjdir: &Path, manifest_files: &[PathBuf]) -> Result<(), OBSCargoError> {
+    info!(
+        "📝 Dry run. Nothing is vendored and nothing is written to {}",
+        args.outdir.display()
+    );
+    let relative = |p: &Path| p.strip_prefix(prjdir).unwrap_or(p).display().to_string();
+    let trees: Vec<(PathBuf, Option<String>, Vec<PathBuf>)> = match args.manifest_mode {
+        ManifestMode::Separate if manifest_files.len() > 1 => manifest_files
+            .iter()
+            .map(|manifest_file| {
+                let treedir = manifest_file.parent().unwrap_or(prjdir).to_path_buf();
+                let tag = tree_tag(args.tag.as_deref(), prjdir, &treedir);
+                (treedir, tag, vec![manifest_file.clone()])
+            })
+            .collect(),
+        _ => vec![(
+            prjdir.to_path_buf(),
+            args.tag.clone(),
+            manifest_files.to_vec(),
+        )],
+    };
+
+    for (treedir, tag, manifests) in &trees {
+        let root_manifest = treedir.join("Cargo.toml");
+        if root_manifest.is_file() && vendor::is_workspace(&root_manifest)? {
+            info!(
+                "📝 Would vendor from the workspace root {}",
+                relative(&root_manifest)
+            );
+        }
+        for manifest in manifests {
+            info!("📝 Would vendor dependencies of {}", relative(manifest));
+        }
+        info!(
+            "📝 Would write the cargo config to {} and the dependencies to {}",
+            relative(&treedir.join(args.config_format.path())),
+            relative(&treedir.join(&args.vendor_dir_name))
+        );
+        info!(
+            "📝 Would create {}",
+            args.outdir
+                .join(vendor::tarball_name(
+                    tag.as_deref(),
+                    args.output_compression()
+                ))
+                .display()
+        );
+    }
+    if let Some(prefix) = &args.prefix {
+        info!(
+            "📝 Would store the tarball entries under {}/",
+            prefix.display()
+        );
+    }
+
+    let platforms = vendor::filter_platforms(args);
+    if platforms.is_empty() {
+        info!("📝 Would vendor dependencies for all platforms");
+    } else {
+        info!(
+            "📝 Would only vendor dependencies for the platforms {}",
+            platforms.join(", ")
+        );
+    }
+    if args.no_dev_deps {
+        info!("📝 Would leave out crates only needed as dev-dependencies");
+    }
+    if args.minimize {
+        info!("📝 Would remove files not needed to build the vendored crates");
+    }
+    if args.is_locked() {
+        info!("📝 Would require the lockfiles to be up to date");
+    } else if args.should_update() {
+        info!("📝 Would update dependencies before vendoring");
+    }
+    Ok(())
+}
+
+/// Calls `f` on every item using at most `jobs` threads. The results are in
+/// the same order as `items`.
+fn run_parallel<T, R, F>(jobs: usize, items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, atomic::Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            break done;
+                        };
+                        done.push((index, f(item)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| match worker.join() {
+                Ok(done) => done,
+                Err(panic) => std::panic::resume_unwind(panic),
+            })
+            .collect()
+    });
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Tag of a separately vendored tree: the directory of its manifest relative
+/// to the project root with `/` replaced by `-`, after the user's tag if any.
+fn tree_tag(base: Option<&str>, prjdir: &Path, treedir: &Path) -> Option<String> {
+    let relative = treedir.strip_prefix(prjdir).unwrap_or(treedir);
+    let dir = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("-");
+    match (base, dir.is_empty()) {
+        (_, true) => base.map(str::to_string),
+        (Some(base), false) => Some(format!("{}-{}", base, dir)),
+        (None, false) => Some(dir),
+    }
+}
+
+/// Inserts the tag before the extension of the audit report file, e.g.
+/// `audit.json` becomes `audit-foo.json`.
+fn tagged_report_path(report: &Path, tag: Option<&str>) -> PathBuf {
+    let Some(tag) = tag else {
+        return report.to_path_buf();
     };
+    let stem = report
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = match report.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, tag, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, tag),
+    };
+    report.with_file_name(name)
+}
+
+/// Vendors the given manifests into `treedir/vendor` with the cargo config
+/// at `treedir/.cargo/config.toml` or, in the legacy format,
+/// `treedir/.cargo/config`. Then archives both relative to `prjdir`.
+fn vendor_tree(
+    args: &Opts,
+    prjdir: &Path,
+    treedir: &Path,
+    tag: Option<&str>,
+    audit_report: Option<&Path>,
+    mut manifest_files: Vec<PathBuf>,
+) -> Result<VendorOutput, OBSCargoError> {
+    // Vendoring against a single member misses the dependencies of its
+    // siblings. If the project is a workspace, vendor from its root and only
+    // keep the extra manifests that are not members of it.
+    let root_manifest = treedir.join("Cargo.toml");
+    if root_manifest.is_file() && vendor::is_workspace(&root_manifest)? {
+        let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+        let mut workspace_manifests: Vec<PathBuf> = vendor::workspace_members(&root_manifest)?
+            .iter()
+            .map(|p| canonical(p))
+            .collect();
+        workspace_manifests.push(canonical(&root_manifest));
+        manifest_files.retain(|manifest_file| {
+            let is_member = workspace_manifests.contains(&canonical(manifest_file));
+            if is_member {
+                debug!(
+                    "Manifest {} is part of the workspace, vendoring from the workspace root instead",
+                    manifest_file.display()
+                );
+            }
+            !is_member
+        });
+        info!(
+            "📚 Vendoring from the workspace root {}",
+            root_manifest.display()
+        );
+        // The last manifest is taken as the one to vendor from.
+        manifest_files.push(root_manifest);
+    }
 
     let Some(first_manifest) = manifest_files.pop() else {
         warn!("Project does not have a discovered manifest or configured paths to Cargo.toml");
@@ -82,11 +945,29 @@ pub fn process_src(args: &Opts, prjdir: &Path) -> Result<(), OBSCargoError> {
     debug!(?first_manifest);
     debug!(?manifest_files);
 
+    let project = match vendor::project_info(&first_manifest) {
+        Ok(project) => {
+            info!(project = %project, "📛 Project is {}", project);
+            Some(project)
+        }
+        Err(err) => {
+            warn!(?err, "⚠️ Unable to read the project name and version");
+            None
+        }
+    };
+
+    // Check before `update`, which would create the lockfile.
+    check_lockfiles_present(args, manifest_files.iter().chain([&first_manifest]))?;
+
     // Setup some common paths we'll use from here out.
-    let outdir = args.outdir.to_owned();
-    let cargo_config = prjdir.join(".cargo/config.toml");
-    let vendor_dir = prjdir.join("vendor");
-    let update = args.update;
+    let cargo_config = treedir.join(args.config_format.path());
+    let vendor_dir = treedir.join(&args.vendor_dir_name);
+    let update = if args.is_locked() && args.should_update() {
+        info!("🔒 Not updating dependencies as `locked` is set");
+        false
+    } else {
+        args.should_update()
+    };
 
     // This is all pre-processing, which is affected by the single/multi Cargo.toml
     // case. We do all this first.
@@ -130,7 +1011,7 @@ pub fn process_src(args: &Opts, prjdir: &Path) -> Result<(), OBSCargoError> {
         hasdeps = should_vendor;
 
         if update {
-            vendor::update(prjdir, &first_manifest)?
+            vendor::update(args, treedir, &first_manifest)?;
         } else {
             warn!(
                 "😥 Disabled update of dependencies. You should enable this for security updates."
@@ -188,10 +1069,9 @@ pub fn process_src(args: &Opts, prjdir: &Path) -> Result<(), OBSCargoError> {
     // Let's ensure the lockfiles are generated even if they don't exist
     // This guarantees that the dependencies used are properly recorded
     for manifest_file in manifest_files.iter() {
-        let manifest_f = manifest_file.canonicalize().map_err(|err| {
-            error!("Failed to canonicalize path: {}", err);
-            OBSCargoError::new(OBSCargoErrorKind::VendorError, err.to_string())
-        })?;
+        let manifest_f = manifest_file
+            .canonicalize()
+            .inspect_err(|err| error!("Failed to canonicalize path: {}", err))?;
 
         let lockfile_path = manifest_f.parent().map(|path_f| path_f.join("Cargo.lock"));
         if let Some(lockfile_p) = lockfile_path {
@@ -200,7 +1080,7 @@ pub fn process_src(args: &Opts, prjdir: &Path) -> Result<(), OBSCargoError> {
                 cargo_locks.push(lockfile_p)
             } else {
                 debug!("Path to extra lockfile not found: {}", lockfile_p.display());
-                if generate_lockfile(manifest_file).is_ok() {
+                if !args.is_locked() && generate_lockfile(args, manifest_file).is_ok() {
                     info!(
                         "🔒 Cargo lockfile created for extra lockfile at path: {}",
                         lockfile_p.display()
@@ -225,7 +1105,7 @@ pub fn process_src(args: &Opts, prjdir: &Path) -> Result<(), OBSCargoError> {
                 "Path to first cargo lock not found: {}",
                 lockfilepath.display()
             );
-            if generate_lockfile(&first_manifest).is_ok() {
+            if !args.is_locked() && generate_lockfile(args, &first_manifest).is_ok() {
                 info!(
                     "🔒 Cargo lockfile created for first lockfile at path: {}",
                     lockfilepath.display()
@@ -239,65 +1119,167 @@ pub fn process_src(args: &Opts, prjdir: &Path) -> Result<(), OBSCargoError> {
 
     debug!("All cargo locks: {:?}", cargo_locks);
 
+    if args.is_locked() {
+        for manifest_file in manifest_files.iter().chain([&first_manifest]) {
+            vendor::verify_lockfile(args, manifest_file)?;
+        }
+    }
+
     // Audit the Cargo.lock file.
-    let reports =
-        perform_cargo_audit(&cargo_locks, &args.i_accept_the_risk).map_err(|rustsec_err| {
+    let database = shared_advisory_db(args.advisory_db.as_deref())?;
+    let reports = perform_cargo_audit(&database, &cargo_locks, &args.i_accept_the_risk).map_err(
+        |rustsec_err| {
             error!(?rustsec_err, "Unable to complete cargo audit");
-            OBSCargoError::new(
+            OBSCargoError::new_with_source(
                 OBSCargoErrorKind::AuditError,
                 "Unable to complete cargo audit".to_string(),
+                rustsec_err,
             )
-        })?;
+        },
+    )?;
 
     debug!(?reports);
 
-    process_reports(reports)?;
+    if let Some(report_path) = audit_report {
+        write_audit_report(report_path, &reports, &args.i_accept_the_risk)?;
+    }
+
+    let audit_findings = audit_findings(&reports, &args.i_accept_the_risk);
+    process_reports(reports, &args.i_accept_the_risk)?;
 
     if hasdeps {
         vendor(
-            prjdir,
+            args,
+            treedir,
             &cargo_config,
             &first_manifest,
             &manifest_files,
-            args.filter,
-            args.respect_lockfile,
         )?;
 
         // Finally, compress everything together.
-        let compression: &Compression = &args.compression;
+        let compression: &Compression = &args.output_compression();
         debug!("Compression is of {}", &compression);
 
-        let mut paths_to_archive: Vec<PathBuf> = vec![cargo_config, vendor_dir.clone()];
+        let mut paths_to_archive: Vec<PathBuf> = vec![cargo_config.clone(), vendor_dir.clone()];
 
         paths_to_archive.append(&mut cargo_locks);
 
         debug!("All paths to archive {:#?}", paths_to_archive);
 
         if vendor_dir.exists() {
-            vendor::compress(
-                outdir,
-                prjdir,
-                &paths_to_archive,
-                compression,
-                args.tag.as_deref(),
-            )?;
+            let tarball = vendor::compress(args, prjdir, tag, &paths_to_archive)?;
+            let stats = vendor::vendor_stats(args, &vendor_dir, &tarball)?;
+            let (checksum, crate_list) = if args.emit_checksum {
+                (
+                    Some(vendor::write_checksum(&tarball)?),
+                    Some(vendor::write_crate_list(&tarball, &vendor_dir)?),
+                )
+            } else {
+                (None, None)
+            };
+            // And we're golden!
+            Ok(VendorOutput {
+                tarball: Some(tarball),
+                cargo_config: cargo_config
+                    .strip_prefix(prjdir)
+                    .ok()
+                    .map(Path::to_path_buf),
+                checksum,
+                crate_list,
+                audit_findings,
+                stats: Some(stats),
+                project,
+            })
         } else {
             error!("Vendor dir does not exist! This is a bug!");
-            return Err(OBSCargoError::new(
+            Err(OBSCargoError::new(
                 OBSCargoErrorKind::VendorError,
                 "Vendor directory not found when attempting to vendor.".to_string(),
-            ));
-        };
+            ))
+        }
     } else {
         warn!("🔥 No dependencies for project were found, skipping vendoring. If you think this is an error, please check your configuration.");
-        return Ok(());
-    };
+        Ok(VendorOutput {
+            tarball: None,
+            cargo_config: None,
+            checksum: None,
+            crate_list: None,
+            audit_findings,
+            stats: None,
+            project,
+        })
+    }
+}
 
-    // And we're golden!
+/// Fails if a manifest has no `Cargo.lock` next to it and `require-lock` is
+/// set. Otherwise only logs that one gets generated.
+fn check_lockfiles_present<'a>(
+    args: &Opts,
+    manifest_files: impl Iterator<Item = &'a PathBuf>,
+) -> Result<(), OBSCargoError> {
+    for manifest_file in manifest_files {
+        let lockfile = manifest_file
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join("Cargo.lock");
+        if lockfile.exists() {
+            continue;
+        }
+        if args.require_lock {
+            error!(?lockfile, "Lockfile is missing");
+            return Err(OBSCargoError::new(
+                OBSCargoErrorKind::LockFileError,
+                format!(
+                    "{} is missing, but `require-lock` is set. Ship a Cargo.lock with the sources, or set `require-lock` to false to have one generated",
+                    lockfile.display()
+                ),
+            ));
+        }
+        if !args.is_locked() {
+            info!(
+                "🔒 No lockfile at {}, generating a new one",
+                lockfile.display()
+            );
+        }
+    }
     Ok(())
 }
 
-pub fn process_globs(src: &Path) -> io::Result<PathBuf> {
+/// Compares two names piecewise, with runs of digits compared as numbers,
+/// so that `foo-1.10.0.tar.gz` sorts after `foo-1.9.0.tar.gz`.
+fn version_cmp(a: &str, b: &str) -> Ordering {
+    fn chunks(s: &str) -> Vec<&str> {
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let bytes = s.as_bytes();
+        for i in 1..=bytes.len() {
+            if i == bytes.len() || bytes[i].is_ascii_digit() != bytes[start].is_ascii_digit() {
+                chunks.push(&s[start..i]);
+                start = i;
+            }
+        }
+        chunks
+    }
+    let (a_chunks, b_chunks) = (chunks(a), chunks(b));
+    for (x, y) in a_chunks.iter().zip(b_chunks.iter()) {
+        let numeric = x.as_bytes()[0].is_ascii_digit() && y.as_bytes()[0].is_ascii_digit();
+        let ordering = if numeric {
+            let (x_trimmed, y_trimmed) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+            x_trimmed
+                .len()
+                .cmp(&y_trimmed.len())
+                .then_with(|| x_trimmed.cmp(y_trimmed))
+        } else {
+            x.cmp(y)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a_chunks.len().cmp(&b_chunks.len()).then_with(|| a.cmp(b))
+}
+
+pub fn process_globs(src: &Path, select: SrcSelect) -> io::Result<PathBuf> {
     let glob_iter = match glob(&src.as_os_str().to_string_lossy()) {
         Ok(gi) => {
             trace!(?gi);
@@ -320,17 +1302,44 @@ pub fn process_globs(src: &Path) -> io::Result<PathBuf> {
             io::Error::new(io::ErrorKind::InvalidInput, "Glob error")
         })?;
 
+    // A directory and a tarball of the same project, e.g. `foo/` next to
+    // `foo.tar.zst`. Picking one by sort order would be a guess.
+    let (dirs, files): (Vec<&PathBuf>, Vec<&PathBuf>) = globs.iter().partition(|p| p.is_dir());
+    if !dirs.is_empty() && !files.is_empty() {
+        let candidates: Vec<String> = globs.iter().map(|p| p.display().to_string()).collect();
+        error!(?candidates, "Src glob matches both directories and files");
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "src `{}` matches both a directory and a file: {}. Change it to only match one of them",
+                src.display(),
+                candidates.join(", ")
+            ),
+        ));
+    }
+
     // There can legitimately be multiple matching files. Generally this happens with
     // tar_scm where you have name-v1.tar and the service reruns and creates
     // name-v2.tar. In this case, we would error if we demand a single match, when what
-    // we really need is to take the *latest*. Thankfully for us, versions in rpm
-    // tar names tend to sort lexicographically, so we can just sort this list and
-    // the last element is the newest. (ie v2 sorts after v1).
-
-    globs.sort_unstable();
+    // we really need is to take the *latest*. Sort so that the one to take is last.
+    // Every order falls back to the name, so the choice never depends on the
+    // order the filesystem lists the files in.
+    match select {
+        SrcSelect::Version => {
+            globs.sort_by(|a, b| version_cmp(&a.to_string_lossy(), &b.to_string_lossy()))
+        }
+        SrcSelect::Mtime => {
+            let mtime = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+            globs.sort_by(|a, b| mtime(a).cmp(&mtime(b)).then_with(|| a.cmp(b)));
+        }
+        SrcSelect::Name => globs.sort(),
+    }
 
     if globs.len() > 1 {
-        warn!("⚠️  Multiple files matched glob");
+        warn!(
+            "⚠️  Multiple files matched glob. Taking the last one by {:?}",
+            select
+        );
         for item in &globs {
             warn!("- {}", item.display());
         }
@@ -349,34 +1358,171 @@ pub fn process_globs(src: &Path) -> io::Result<PathBuf> {
         })
 }
 
+/// Runs `cargo <subcommand> <options>` in `curdir` and returns its standard output.
+///
+/// With `offline` set, cargo gets `--offline` and `CARGO_NET_OFFLINE` is
+/// exported, so external subcommands like vendor-filterer stay offline too.
+/// A run taking longer than `timeout` is killed. Failures that look like network
+/// hiccups are retried up to `retries` times with exponential backoff.
 pub fn cargo_command<S: AsRef<OsStr>>(
     subcommand: &str,
     options: &[S],
     curdir: impl AsRef<Path>,
+    opts: &Opts,
+) -> Result<String, ExecutionError> {
+    // Honor `CARGO` like cargo's own subcommands do.
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    retry_cargo(&cargo, subcommand, options, curdir.as_ref(), opts)
+}
+
+fn retry_cargo<S: AsRef<OsStr>>(
+    cargo: &OsStr,
+    subcommand: &str,
+    options: &[S],
+    curdir: &Path,
+    opts: &Opts,
+) -> Result<String, ExecutionError> {
+    let timeout = opts.timeout.map(Duration::from_secs);
+    let mut attempt = 0;
+    loop {
+        match run_cargo(
+            cargo,
+            subcommand,
+            options,
+            curdir,
+            opts.is_offline(),
+            timeout,
+        ) {
+            Err(err) if attempt < opts.retries && err.is_transient() => {
+                let delay = Duration::from_secs(CARGO_RETRY_BASE_DELAY_SECS << attempt.min(10));
+                attempt += 1;
+                warn!(
+                    "🔁 {} failed, probably because of the network. Retrying in {}s ({}/{})",
+                    err.command,
+                    delay.as_secs(),
+                    attempt,
+                    opts.retries
+                );
+                std::thread::sleep(delay);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Reads everything from a pipe of a child process.
+fn read_pipe(pipe: Option<impl io::Read>) -> Vec<u8> {
+    let mut buf = Vec::new();
+    if let Some(mut pipe) = pipe {
+        if let Err(err) = pipe.read_to_end(&mut buf) {
+            warn!(?err, "⚠️ Unable to read output of cargo");
+        }
+    }
+    buf
+}
+
+/// Waits for `child`, killing it once `timeout` has passed. Returns `None`
+/// if it was killed.
+fn wait_with_timeout(
+    child: &mut std::process::Child,
+    timeout: Option<Duration>,
+) -> io::Result<Option<std::process::ExitStatus>> {
+    let Some(timeout) = timeout else {
+        return child.wait().map(Some);
+    };
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if started.elapsed() >= timeout {
+            // Kill the whole process group, cargo's own children (rustc,
+            // build scripts, git) would otherwise keep running and keep
+            // our pipes open.
+            let pgid = child.id() as libc::pid_t;
+            // SAFETY: killpg only sends a signal, the group is the one
+            // `run_cargo` created for this child, which we have not reaped yet.
+            if unsafe { libc::killpg(pgid, libc::SIGKILL) } != 0 {
+                child.kill()?;
+            }
+            child.wait()?;
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+fn run_cargo<S: AsRef<OsStr>>(
+    cargo: &OsStr,
+    subcommand: &str,
+    options: &[S],
+    curdir: &Path,
+    offline: bool,
+    timeout: Option<Duration>,
 ) -> Result<String, ExecutionError> {
-    let cmd = std::process::Command::new("cargo")
+    let mut command = std::process::Command::new(cargo);
+    if offline {
+        // Before the subcommand, cargo takes it there for external ones too.
+        command.arg("--offline").env("CARGO_NET_OFFLINE", "true");
+    }
+    command
         .arg(subcommand)
         .args(options.iter().map(|s| s.as_ref()))
-        .current_dir(curdir.as_ref())
-        .output()
-        .map_err(|e| {
-            error!(err = ?e, "Unable to build cargo command");
-            ExecutionError {
-                command: format!("cargo {}", subcommand),
-                exit_code: Some(-1),
-                stdoutput: "".to_string(),
-            }
-        })?;
-    trace!(?cmd);
-    let stdoutput = String::from_utf8_lossy(&cmd.stdout);
-    let stderrput = String::from_utf8_lossy(&cmd.stderr);
-    if !cmd.status.success() {
+        .current_dir(curdir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // In its own process group, so a timeout can kill everything it spawned.
+        .process_group(0);
+    let spawn_error = |e: io::Error| {
+        error!(err = ?e, "Unable to build cargo command");
+        ExecutionError {
+            command: format!("cargo {}", subcommand),
+            exit_code: Some(-1),
+            stdoutput: "".to_string(),
+            stderroutput: e.to_string(),
+            timed_out: false,
+        }
+    };
+    let mut child = command.spawn().map_err(spawn_error)?;
+    // Read both pipes while waiting, cargo blocks once a pipe is full.
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    let (status, stdout, stderr) = std::thread::scope(|scope| {
+        let stdout = scope.spawn(move || read_pipe(stdout));
+        let stderr = scope.spawn(move || read_pipe(stderr));
+        let status = wait_with_timeout(&mut child, timeout);
+        let join = |reader: std::thread::ScopedJoinHandle<'_, Vec<u8>>| match reader.join() {
+            Ok(buf) => buf,
+            Err(panic) => std::panic::resume_unwind(panic),
+        };
+        (status, join(stdout), join(stderr))
+    });
+    let status = status.map_err(spawn_error)?;
+    trace!(?status);
+    let stdoutput = String::from_utf8_lossy(&stdout);
+    let stderrput = String::from_utf8_lossy(&stderr);
+    let Some(status) = status else {
+        error!("🛑 cargo {} timed out", subcommand);
+        return Err(ExecutionError {
+            command: format!("cargo {}", subcommand),
+            exit_code: None,
+            stdoutput: stdoutput.to_string(),
+            stderroutput: format!(
+                "{}\nerror: killed after {}s",
+                stderrput,
+                timeout.map(|t| t.as_secs()).unwrap_or_default()
+            ),
+            timed_out: true,
+        });
+    };
+    if !status.success() {
         error!(?stdoutput);
         error!(?stderrput);
         return Err(ExecutionError {
             command: format!("cargo {}", subcommand),
-            exit_code: cmd.status.code(),
+            exit_code: status.code(),
             stdoutput: stdoutput.to_string(),
+            stderroutput: stderrput.to_string(),
+            timed_out: false,
         });
     };
     debug!(?stdoutput);
@@ -385,12 +1531,54 @@ pub fn cargo_command<S: AsRef<OsStr>>(
     Ok(stdoutput.to_string())
 }
 
+#[derive(Clone)]
 pub struct ExecutionError {
     pub command: String,
     pub exit_code: Option<i32>,
     pub stdoutput: String,
+    pub stderroutput: String,
+    /// The command was killed because it ran longer than the timeout.
+    pub timed_out: bool,
+}
+
+impl ExecutionError {
+    /// The `error:` lines cargo printed. These name the culprit, e.g. the
+    /// crate that is not in the local cache when running offline.
+    pub fn cargo_errors(&self) -> String {
+        self.stderroutput
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("error:"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The last lines cargo wrote to stderr. With `-vv` the output is long,
+    /// but what went wrong is at the end.
+    pub fn stderr_tail(&self) -> String {
+        let lines: Vec<&str> = self
+            .stderroutput
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        lines[lines.len().saturating_sub(CARGO_STDERR_TAIL_LINES)..].join("\n")
+    }
 }
 
+impl ExecutionError {
+    /// Whether running the command again could succeed, e.g. after a DNS or
+    /// TLS hiccup. Resolution errors are never transient.
+    pub fn is_transient(&self) -> bool {
+        let stderr = &self.stderroutput;
+        if CARGO_PERMANENT_ERRORS.iter().any(|e| stderr.contains(e)) {
+            return false;
+        }
+        self.timed_out || CARGO_TRANSIENT_ERRORS.iter().any(|e| stderr.contains(e))
+    }
+}
+
+impl std::error::Error for ExecutionError {}
+
 impl Debug for ExecutionError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let msg = format!(
@@ -415,3 +1603,624 @@ impl Display for ExecutionError {
         write!(f, "{}", msg)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn touch(dir: &Path, names: &[&str]) {
+        for name in names {
+            fs::write(dir.join(name), "").unwrap();
+        }
+    }
+
+    fn selected(dir: &Path, pattern: &str, select: SrcSelect) -> String {
+        let chosen = process_globs(&dir.join(pattern), select).unwrap();
+        chosen.file_name().unwrap().to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn version_cmp_digit_runs() {
+        assert_eq!(version_cmp("foo-1.10.0", "foo-1.9.0"), Ordering::Greater);
+        assert_eq!(version_cmp("foo-1.9.0", "foo-1.10.0"), Ordering::Less);
+        assert_eq!(version_cmp("foo-2.0.0", "foo-10.0.0"), Ordering::Less);
+        assert_eq!(version_cmp("foo-1.0.0", "foo-1.0.0.1"), Ordering::Less);
+        assert_eq!(
+            version_cmp("foo-1.0.0-rc1", "foo-1.0.0-rc2"),
+            Ordering::Less
+        );
+        assert_eq!(version_cmp("foo-1.2.3", "foo-1.2.3"), Ordering::Equal);
+    }
+
+    #[test]
+    fn version_cmp_leading_zeros() {
+        assert_eq!(version_cmp("foo-1.010", "foo-1.9"), Ordering::Greater);
+        assert_eq!(version_cmp("foo-1.009", "foo-1.10"), Ordering::Less);
+        // Equal as numbers, the longer name sorts last.
+        assert_eq!(version_cmp("foo-1.000", "foo-1.0"), Ordering::Greater);
+    }
+
+    #[test]
+    fn version_cmp_ties_broken_by_name() {
+        // Same version, so the plain name decides and the order stays total.
+        assert_eq!(version_cmp("foo-01.0", "foo-1.0"), Ordering::Less);
+        assert_eq!(version_cmp("foo-1.0", "foo-01.0"), Ordering::Greater);
+        assert_eq!(version_cmp("bar-1.0", "foo-1.0"), Ordering::Less);
+    }
+
+    #[test]
+    fn highest_version_selected() {
+        let dir = tempfile::tempdir().unwrap();
+        touch(dir.path(), &["foo-1.9.0.tar.gz", "foo-1.10.0.tar.gz"]);
+        assert_eq!(
+            selected(dir.path(), "foo-*.tar.gz", SrcSelect::Version),
+            "foo-1.10.0.tar.gz"
+        );
+        assert_eq!(
+            selected(dir.path(), "foo-*.tar.gz", SrcSelect::Name),
+            "foo-1.9.0.tar.gz"
+        );
+    }
+
+    #[test]
+    fn newest_mtime_selected() {
+        let dir = tempfile::tempdir().unwrap();
+        touch(dir.path(), &["foo-1.9.0.tar.gz", "foo-1.10.0.tar.gz"]);
+        let epoch = std::time::SystemTime::UNIX_EPOCH;
+        let set_mtime = |name: &str, secs: u64| {
+            fs::File::options()
+                .write(true)
+                .open(dir.path().join(name))
+                .unwrap()
+                .set_modified(epoch + Duration::from_secs(secs))
+                .unwrap();
+        };
+        set_mtime("foo-1.10.0.tar.gz", 1_000);
+        set_mtime("foo-1.9.0.tar.gz", 2_000);
+        assert_eq!(
+            selected(dir.path(), "foo-*.tar.gz", SrcSelect::Mtime),
+            "foo-1.9.0.tar.gz"
+        );
+        // Equal mtimes fall back to the name.
+        set_mtime("foo-1.9.0.tar.gz", 1_000);
+        assert_eq!(
+            selected(dir.path(), "foo-*.tar.gz", SrcSelect::Mtime),
+            "foo-1.9.0.tar.gz"
+        );
+    }
+
+    #[test]
+    fn directory_and_tarball_both_matched() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("foo")).unwrap();
+        touch(dir.path(), &["foo.tar.zst"]);
+        for select in [SrcSelect::Version, SrcSelect::Mtime, SrcSelect::Name] {
+            let err = process_globs(&dir.path().join("foo*"), select).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            let message = err.to_string();
+            assert!(
+                message.contains("matches both a directory and a file"),
+                "{}",
+                message
+            );
+            let candidates = format!(
+                ": {}, {}.",
+                dir.path().join("foo").display(),
+                dir.path().join("foo.tar.zst").display()
+            );
+            assert!(message.contains(&candidates), "{}", message);
+        }
+        // Either one alone is fine.
+        assert_eq!(
+            selected(dir.path(), "foo.tar.*", SrcSelect::default()),
+            "foo.tar.zst"
+        );
+        assert_eq!(selected(dir.path(), "fo[o]", SrcSelect::default()), "foo");
+    }
+
+    #[test]
+    fn collected_failures_stay_on_one_line() {
+        let names = ["a".to_string(), "b".to_string(), "c".to_string()];
+        let cargo_failed = OBSCargoError::new(
+            OBSCargoErrorKind::LockFileError,
+            "cargo update failed".to_string(),
+        )
+        .with_details(["error: no matching package named `foo` found".to_string()]);
+        let missing = OBSCargoError::new(
+            OBSCargoErrorKind::VendorError,
+            "Unsupported archive format".to_string(),
+        );
+        let results = [Err(cargo_failed), Ok(Vec::new()), Err(missing)];
+        let err = collect_outputs("sources", &names, results).unwrap_err();
+        assert!(matches!(err.kind(), OBSCargoErrorKind::LockFileError));
+        assert_eq!(
+            err.to_string(),
+            "lockfile generation failed: vendoring failed for 2 of 3 sources: a, c"
+        );
+        assert_eq!(
+            err.details(),
+            [
+                "a: cargo update failed",
+                "  error: no matching package named `foo` found",
+                "c: Unsupported archive format",
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_symlinks_survive_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(src.join("crate/tests")).unwrap();
+        fs::write(src.join("crate/LICENSE-MIT"), "MIT").unwrap();
+        let links = [
+            ("crate/LICENSE", "LICENSE-MIT"),
+            ("crate/tests/LICENSE", "../LICENSE-MIT"),
+        ];
+        for (link, target) in links {
+            std::os::unix::fs::symlink(target, src.join(link)).unwrap();
+        }
+
+        let copy = dir.path().join("copy");
+        copy_dir_all(&src, &copy, &CopyFilter::new(&[]).unwrap()).unwrap();
+        let tarball = dir.path().join("links.tar.zst");
+        let options = compress::ArchiveOptions {
+            reproducible: true,
+            ..Default::default()
+        };
+        compress::tarzst(&tarball, &copy, &[copy.join("crate")], options).unwrap();
+        let out = dir.path().join("out");
+        decompress::tarzst(&out, &tarball).unwrap();
+
+        for root in [&copy, &out] {
+            for (link, target) in links {
+                let link = root.join(link);
+                assert!(
+                    link.symlink_metadata().unwrap().is_symlink(),
+                    "{}",
+                    link.display()
+                );
+                assert_eq!(fs::read_link(&link).unwrap(), Path::new(target));
+                assert_eq!(fs::read_to_string(&link).unwrap(), "MIT");
+            }
+        }
+    }
+
+    /// A fake cargo that logs each call to `calls` and then runs `body`.
+    fn mock_cargo(dir: &Path, body: &str) -> PathBuf {
+        let script = dir.join("cargo");
+        let calls = dir.join("calls");
+        fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\ndate +%s%N >> '{}'\nn=$(wc -l < '{}')\n{}\n",
+                calls.display(),
+                calls.display(),
+                body
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        script
+    }
+
+    /// When the mock was called, in nanoseconds since the epoch.
+    fn calls(dir: &Path) -> Vec<u128> {
+        fs::read_to_string(dir.join("calls"))
+            .unwrap_or_default()
+            .lines()
+            .map(|line| line.parse().unwrap())
+            .collect()
+    }
+
+    fn run(cargo: &Path, args: &[&str]) -> Result<String, ExecutionError> {
+        let required = ["cargo_vendor", "--src", ".", "--outdir", "."];
+        let opts = Opts::parse_from(required.iter().chain(args));
+        retry_cargo(
+            cargo.as_os_str(),
+            "vendor",
+            &["--locked"],
+            Path::new("."),
+            &opts,
+        )
+    }
+
+    #[test]
+    fn transient_failures_retried_with_backoff() {
+        let dir = tempfile::tempdir().unwrap();
+        let cargo = mock_cargo(
+            dir.path(),
+            "if [ $n -le 2 ]; then\n  echo 'error: spurious network error: Could not resolve host' >&2\n  exit 101\nfi\necho vendored",
+        );
+        let output = run(&cargo, &["--retries", "3"]).unwrap();
+        assert_eq!(output, "vendored\n");
+        let calls = calls(dir.path());
+        assert_eq!(calls.len(), 3);
+        // The delay doubles with each attempt.
+        let second = u128::from(CARGO_RETRY_BASE_DELAY_SECS) * 1_000_000_000;
+        assert!(calls[1] - calls[0] >= second, "{:?}", calls);
+        assert!(calls[2] - calls[1] >= 2 * second, "{:?}", calls);
+    }
+
+    #[test]
+    fn retries_exhausted() {
+        let dir = tempfile::tempdir().unwrap();
+        let cargo = mock_cargo(
+            dir.path(),
+            "echo 'error: failed to download from `https://index.crates.io`' >&2\nexit 101",
+        );
+        let err = run(&cargo, &["--retries", "1"]).unwrap_err();
+        assert_eq!(err.exit_code, Some(101));
+        assert_eq!(calls(dir.path()).len(), 2);
+    }
+
+    #[test]
+    fn resolution_error_not_retried() {
+        let dir = tempfile::tempdir().unwrap();
+        let cargo = mock_cargo(
+            dir.path(),
+            "echo 'error: failed to select a version for the requirement `hex = \"^9\"`' >&2\necho 'Caused by: failed to download' >&2\nexit 101",
+        );
+        let err = run(&cargo, &["--retries", "3"]).unwrap_err();
+        assert!(!err.is_transient());
+        assert_eq!(calls(dir.path()).len(), 1);
+    }
+
+    #[test]
+    fn timeout_kills_process_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let pidfile = dir.path().join("child.pid");
+        // Like cargo running rustc: a child that holds on to our pipes.
+        let cargo = mock_cargo(
+            dir.path(),
+            &format!("sleep 60 &\necho $! > '{}'\nwait", pidfile.display()),
+        );
+        let started = Instant::now();
+        let err = run(&cargo, &["--timeout", "1", "--retries", "0"]).unwrap_err();
+        assert!(err.timed_out);
+        assert!(
+            err.stderroutput.contains("killed after 1s"),
+            "{}",
+            err.stderroutput
+        );
+        assert!(started.elapsed() < Duration::from_secs(30));
+        assert_eq!(calls(dir.path()).len(), 1);
+
+        let pid = fs::read_to_string(&pidfile).unwrap();
+        let stat = PathBuf::from("/proc").join(pid.trim()).join("stat");
+        // Gone, or a zombie waiting for init to reap it.
+        let alive = || {
+            fs::read_to_string(&stat).is_ok_and(|stat| {
+                stat.rsplit_once(") ")
+                    .is_some_and(|(_, rest)| !rest.starts_with('Z'))
+            })
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while alive() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert!(!alive(), "child of the timed out cargo is still running");
+    }
+
+    #[test]
+    fn junk_entries_ignored() {
+        for junk in ["._foo", ".github", "pax_global_header", "__MACOSX"] {
+            assert!(is_junk_entry(OsStr::new(junk)), "{}", junk);
+        }
+        for real in ["foo", "Cargo.toml", "foo.", "MACOSX"] {
+            assert!(!is_junk_entry(OsStr::new(real)), "{}", real);
+        }
+        let entries = |entries: &[(&str, bool)]| {
+            entries
+                .iter()
+                .map(|(name, is_dir)| (name.to_string(), *is_dir))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            Layout::classify(entries(&[
+                ("foo-1.0.0", true),
+                ("pax_global_header", false),
+                ("._foo-1.0.0", false),
+                ("__MACOSX", true),
+            ])),
+            Layout::TopLevelDir("foo-1.0.0".to_string())
+        );
+        assert_eq!(
+            Layout::classify(entries(&[("foo", true), ("bar", true)])),
+            Layout::MultipleDirs(vec!["bar".to_string(), "foo".to_string()])
+        );
+        assert_eq!(
+            Layout::classify(entries(&[("src", true), ("README.md", false)])),
+            Layout::Mixed
+        );
+        assert_eq!(
+            Layout::classify(entries(&[("pax_global_header", false)])),
+            Layout::Empty
+        );
+    }
+
+    /// Writes an uncompressed tarball with the given files to `path`.
+    fn plain_tarball(path: &Path, files: &[(&str, &[u8])]) {
+        let mut builder = tar::Builder::new(fs::File::create(path).unwrap());
+        for (name, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn flat_tarball_root_is_project_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let tarball = dir.path().join("foo-1.0.0.tar");
+        plain_tarball(
+            &tarball,
+            &[
+                ("Cargo.toml", b"[package]\nname = \"foo\"\n"),
+                ("src/main.rs", b"fn main() {}\n"),
+            ],
+        );
+        let workdir = dir.path().join("workdir");
+        decompress::vanilla(&workdir, &tarball).unwrap();
+        assert_eq!(top_level_dir(&workdir).unwrap(), workdir);
+    }
+
+    #[test]
+    fn nested_archive_rejected() {
+        use std::io::Write;
+        let dir = tempfile::tempdir().unwrap();
+        let inner = dir.path().join("inner.tar");
+        plain_tarball(&inner, &[("foo-1.0.0/Cargo.toml", b"[package]\n")]);
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&fs::read(&inner).unwrap()).unwrap();
+        let inner_gz = encoder.finish().unwrap();
+        let outer = dir.path().join("foo-1.0.0.tar");
+        plain_tarball(&outer, &[("foo-1.0.0.tar.gz", &inner_gz)]);
+
+        let workdir = dir.path().join("workdir");
+        decompress::vanilla(&workdir, &outer).unwrap();
+        let err = top_level_dir(&workdir).unwrap_err();
+        assert!(matches!(err.kind(), OBSCargoErrorKind::VendorError));
+        assert!(err.message().contains("nested archive"), "{}", err);
+        assert!(err.message().contains("foo-1.0.0.tar.gz"), "{}", err);
+        assert!(err.message().contains("gz-compressed"), "{}", err);
+
+        // An uncompressed tarball inside is named as such.
+        let outer = dir.path().join("bar-1.0.0.tar");
+        plain_tarball(&outer, &[("inner.tar", &fs::read(&inner).unwrap())]);
+        let workdir = dir.path().join("workdir-plain");
+        decompress::vanilla(&workdir, &outer).unwrap();
+        let err = top_level_dir(&workdir).unwrap_err();
+        assert!(err.message().contains("which is a tarball"), "{}", err);
+    }
+
+    #[test]
+    fn source_checksum_verified() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("foo-1.0.0.tar.gz");
+        fs::write(&src, b"abc").unwrap();
+        let digest = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        verify_sha256(&src, digest).unwrap();
+        let wrong = "0".repeat(64);
+        let err = verify_sha256(&src, &wrong).unwrap_err();
+        assert!(matches!(err.kind(), OBSCargoErrorKind::VendorError));
+        assert!(err.message().contains(digest), "{}", err);
+        assert!(err.message().contains(&wrong), "{}", err);
+        let err = verify_sha256(&dir.path().join("missing.tar.gz"), digest).unwrap_err();
+        assert!(err.message().contains("failed to read"), "{}", err);
+    }
+
+    #[test]
+    fn src_list_parsed() {
+        let dir = tempfile::tempdir().unwrap();
+        let list = dir.path().join("sources");
+        fs::write(
+            &list,
+            "# Vendored for the package\n\
+             \n\
+             foo-1.0.0.tar.zst\n\
+             \tbar-2.1.tar.gz   bar\n\
+             vendor/baz-*.tar.xz\n\
+             qux\n",
+        )
+        .unwrap();
+        let listed: Vec<(String, String)> = read_src_list(&list)
+            .unwrap()
+            .into_iter()
+            .map(|listed| (listed.src.display().to_string(), listed.tag))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("foo-1.0.0.tar.zst".to_string(), "foo-1.0.0".to_string()),
+                ("bar-2.1.tar.gz".to_string(), "bar".to_string()),
+                ("vendor/baz-*.tar.xz".to_string(), "baz-".to_string()),
+                ("qux".to_string(), "qux".to_string()),
+            ]
+        );
+
+        for (contents, message) in [
+            ("# nothing\n\n", "does not list any sources"),
+            ("foo.tar.gz foo extra\n", "line 1 of src list"),
+            (
+                "foo.tar.gz\nother/foo.tar.xz\n",
+                "would both get the tag `foo`",
+            ),
+        ] {
+            fs::write(&list, contents).unwrap();
+            let err = read_src_list(&list).unwrap_err();
+            assert!(err.message().contains(message), "{}", err);
+        }
+        assert!(read_src_list(&dir.path().join("missing")).is_err());
+    }
+
+    /// Options for vendoring `.` to `outdir`, followed by `args`.
+    fn outdir_opts(outdir: &Path, args: &[&str]) -> Opts {
+        let outdir = outdir.to_string_lossy();
+        let required = ["cargo_vendor", "--src", ".", "--outdir", &outdir];
+        Opts::parse_from(required.iter().chain(args))
+    }
+
+    #[test]
+    fn outdir_checked() {
+        let dir = tempfile::tempdir().unwrap();
+        check_outdir(&outdir_opts(dir.path(), &[])).unwrap();
+
+        let missing = dir.path().join("missing/out");
+        let err = check_outdir(&outdir_opts(&missing, &[])).unwrap_err();
+        assert!(err.message().contains("does not exist"), "{}", err);
+        assert!(err.message().contains("create-outdir"), "{}", err);
+        check_outdir(&outdir_opts(
+            &missing,
+            &["--create-outdir", "true", "--dry-run", "true"],
+        ))
+        .unwrap();
+        assert!(!missing.exists());
+        check_outdir(&outdir_opts(&missing, &["--create-outdir", "true"])).unwrap();
+        assert!(missing.is_dir());
+
+        let file = dir.path().join("file");
+        fs::write(&file, "").unwrap();
+        let err = check_outdir(&outdir_opts(&file, &[])).unwrap_err();
+        assert!(err.message().contains("is not a directory"), "{}", err);
+
+        let read_only = dir.path().join("read-only");
+        fs::create_dir(&read_only).unwrap();
+        fs::set_permissions(&read_only, fs::Permissions::from_mode(0o555)).unwrap();
+        // Root can write anyway, which `check_outdir` finds out the same way.
+        let writable = tempfile::tempfile_in(&read_only).is_ok();
+        let result = check_outdir(&outdir_opts(&read_only, &[]));
+        fs::set_permissions(&read_only, fs::Permissions::from_mode(0o755)).unwrap();
+        if writable {
+            result.unwrap();
+        } else {
+            let err = result.unwrap_err();
+            assert!(err.message().contains("is not writable"), "{}", err);
+        }
+    }
+
+    /// Files below `root`, relative to it and sorted.
+    fn files_below(root: &Path) -> Vec<String> {
+        let mut files: Vec<String> = walkdir::WalkDir::new(root)
+            .into_iter()
+            .map(Result::unwrap)
+            .filter(|entry| !entry.file_type().is_dir())
+            .map(|entry| {
+                let rel = entry.path().strip_prefix(root).unwrap();
+                rel.to_string_lossy().to_string()
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn copy_filter_applied() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        for file in [
+            "Cargo.toml",
+            "src/main.rs",
+            "src/main.rs.orig",
+            "target/debug/foo",
+            ".git/HEAD",
+            "crates/a/target/keep.rs",
+            "crates/a/.git/HEAD",
+            "docs/big.pdf",
+            "docs/guide.md",
+            "fixtures/huge.bin",
+        ] {
+            let path = src.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let filter = CopyFilter::new(&["*.pdf".to_string(), "/fixtures/".to_string()]).unwrap();
+        let copy = dir.path().join("copy");
+        copy_dir_all(&src, &copy, &filter).unwrap();
+        assert_eq!(
+            files_below(&copy),
+            [
+                "Cargo.toml",
+                "crates/a/target/keep.rs",
+                "docs/guide.md",
+                "src/main.rs",
+            ]
+        );
+        assert!(!copy.join("target").exists());
+        assert!(!copy.join("fixtures").exists());
+        assert!(CopyFilter::new(&["[".to_string()]).is_err());
+    }
+
+    #[test]
+    fn missing_lockfile_required() {
+        let dir = tempfile::tempdir().unwrap();
+        let locked = dir.path().join("locked/Cargo.toml");
+        let unlocked = dir.path().join("unlocked/Cargo.toml");
+        for manifest in [&locked, &unlocked] {
+            fs::create_dir_all(manifest.parent().unwrap()).unwrap();
+            fs::write(manifest, "[package]\nname = \"foo\"\n").unwrap();
+        }
+        fs::write(dir.path().join("locked/Cargo.lock"), "version = 3\n").unwrap();
+        let required = outdir_opts(dir.path(), &["--require-lock", "true"]);
+        check_lockfiles_present(&required, [&locked].into_iter()).unwrap();
+        let err = check_lockfiles_present(&required, [&locked, &unlocked].into_iter()).unwrap_err();
+        assert!(matches!(err.kind(), OBSCargoErrorKind::LockFileError));
+        assert!(
+            err.message()
+                .contains(&dir.path().join("unlocked/Cargo.lock").display().to_string()),
+            "{}",
+            err
+        );
+        // Without `require-lock` a lockfile gets generated.
+        let generated = outdir_opts(dir.path(), &[]);
+        check_lockfiles_present(&generated, [&locked, &unlocked].into_iter()).unwrap();
+    }
+
+    #[test]
+    fn parallel_results_in_input_order() {
+        let items: Vec<u64> = (0..16).collect();
+        for jobs in [0, 1, 2, 3, 16, 64] {
+            let threads = std::sync::Mutex::new(std::collections::HashSet::new());
+            let results = run_parallel(jobs, &items, |item| {
+                threads.lock().unwrap().insert(std::thread::current().id());
+                // Earlier items take longer, so they finish last.
+                std::thread::sleep(std::time::Duration::from_millis(16 - item));
+                item * 2
+            });
+            let doubled: Vec<u64> = items.iter().map(|item| item * 2).collect();
+            assert_eq!(results, doubled, "jobs {}", jobs);
+            assert!(threads.into_inner().unwrap().len() <= jobs.max(1));
+        }
+        assert!(run_parallel(4, &[] as &[u64], |item| *item).is_empty());
+    }
+
+    #[test]
+    fn projects_side_by_side() {
+        let dir = tempfile::tempdir().unwrap();
+        for manifest in [
+            "zeta/Cargo.toml",
+            "alpha/Cargo.toml",
+            ".github/Cargo.toml",
+            "nested/inner/Cargo.toml",
+        ] {
+            let manifest = dir.path().join(manifest);
+            fs::create_dir_all(manifest.parent().unwrap()).unwrap();
+            fs::write(manifest, "[package]\nname = \"foo\"\n").unwrap();
+        }
+        fs::create_dir(dir.path().join("docs")).unwrap();
+        fs::write(dir.path().join("README.md"), "").unwrap();
+        assert_eq!(
+            find_project_manifests(dir.path()),
+            [
+                dir.path().join("alpha/Cargo.toml"),
+                dir.path().join("zeta/Cargo.toml")
+            ]
+        );
+        assert!(find_project_manifests(&dir.path().join("docs")).is_empty());
+        assert!(find_project_manifests(&dir.path().join("missing")).is_empty());
+    }
+}
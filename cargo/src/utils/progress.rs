@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: MPL-2.0
+
+// Copyright (C) 2024 To all Contributors of this project listed in CONTRIBUTORS.md
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::consts::PROGRESS_INTERVAL_SECS;
+
+#[allow(unused_imports)]
+use tracing::{debug, error, info, trace, warn, Level};
+
+/// Counts the entries and bytes a long-running step has processed and logs
+/// them every `PROGRESS_INTERVAL_SECS`, so big projects do not look stuck.
+#[derive(Debug)]
+pub struct Progress {
+    phase: &'static str,
+    entries: u64,
+    bytes: u64,
+    started: Instant,
+    last_report: Instant,
+}
+
+impl Progress {
+    pub fn new(phase: &'static str) -> Self {
+        let now = Instant::now();
+        Self {
+            phase,
+            entries: 0,
+            bytes: 0,
+            started: now,
+            last_report: now,
+        }
+    }
+
+    /// Records one more entry of `bytes` size.
+    pub fn advance(&mut self, bytes: u64) {
+        self.entries += 1;
+        self.bytes += bytes;
+        if self.last_report.elapsed() >= Duration::from_secs(PROGRESS_INTERVAL_SECS) {
+            info!(
+                phase = self.phase,
+                entries = self.entries,
+                bytes = self.bytes,
+                "⏳ {}: {} entries, {} so far",
+                self.phase,
+                self.entries,
+                human_bytes(self.bytes)
+            );
+            self.last_report = Instant::now();
+        }
+    }
+
+    pub fn finish(&self) {
+        info!(
+            phase = self.phase,
+            entries = self.entries,
+            bytes = self.bytes,
+            "⌛ {}: {} entries, {} in {:.1}s",
+            self.phase,
+            self.entries,
+            human_bytes(self.bytes),
+            self.started.elapsed().as_secs_f64()
+        );
+    }
+}
+
+/// Sets the flag when dropped, also when unwinding from a panic.
+struct SetOnDrop<'a>(&'a AtomicBool);
+
+impl Drop for SetOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Runs `run` while logging how many entries `dir` has every
+/// `PROGRESS_INTERVAL_SECS`. Used for steps done by a child process, like
+/// `cargo vendor` filling the vendor directory.
+pub fn watch_dir<T>(phase: &'static str, dir: &Path, run: impl FnOnce() -> T) -> T {
+    let done = AtomicBool::new(false);
+    let started = Instant::now();
+    let count = || {
+        fs::read_dir(dir)
+            .map(|entries| entries.count())
+            .unwrap_or(0)
+    };
+    let result = std::thread::scope(|scope| {
+        scope.spawn(|| {
+            let mut last_report = Instant::now();
+            while !done.load(Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_millis(100));
+                if last_report.elapsed() >= Duration::from_secs(PROGRESS_INTERVAL_SECS) {
+                    let entries = count();
+                    info!(phase, entries, "⏳ {}: {} entries so far", phase, entries);
+                    last_report = Instant::now();
+                }
+            }
+        });
+        let _done = SetOnDrop(&done);
+        run()
+    });
+    let entries = count();
+    info!(
+        phase,
+        entries,
+        "⌛ {}: {} entries in {:.1}s",
+        phase,
+        entries,
+        started.elapsed().as_secs_f64()
+    );
+    result
+}
+
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
@@ -9,14 +9,23 @@
 use std::collections::BTreeMap;
 use std::ffi::OsString;
 use std::fs;
+use std::io;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+use crate::audit::AuditFinding;
+use crate::cli::Opts;
+use crate::consts::REGISTRY_MIRROR_SOURCE;
 use crate::errors::OBSCargoError;
 use crate::errors::OBSCargoErrorKind;
 use crate::utils::cargo_command;
+use crate::utils::compress;
+use crate::utils::compress::CompressionExt;
+use crate::utils::decompress;
+use crate::utils::minimize;
+use crate::utils::progress;
+use crate::utils::ExecutionError;
 use libroast::common::Compression;
-use libroast::compress;
 
 use serde::Deserialize;
 use serde::Serialize;
@@ -24,30 +33,84 @@ use serde::Serialize;
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn, Level};
 
+/// Turns a failed cargo call into an error of `kind` with the tail of cargo's
+/// stderr as details, which names the failing crate or version conflict.
+fn cargo_error(kind: OBSCargoErrorKind, message: &str, e: &ExecutionError) -> OBSCargoError {
+    error!(err = %e);
+    OBSCargoError::new_with_source(kind, message.to_string(), e.clone())
+        .with_details(e.stderr_tail().lines().map(String::from))
+}
+
+/// Turns a failed network-facing cargo call into an error. When running
+/// offline, cargo's own error lines are kept so the missing crate is named.
+fn network_error(opts: &Opts, e: &ExecutionError) -> OBSCargoError {
+    let message = if opts.is_offline() {
+        format!(
+            "{} failed in offline mode. Make sure all crates are in the local cargo cache. {}",
+            e.command,
+            e.cargo_errors()
+        )
+    } else {
+        format!("{} failed", e.command)
+    };
+    cargo_error(OBSCargoErrorKind::VendorError, &message, e)
+}
+
+/// Runs `cargo update` for `manifest_path` and logs which crates changed
+/// their locked version. The changes are returned as well.
 pub fn update(
+    opts: &Opts,
     prjdir: impl AsRef<Path>,
     manifest_path: impl AsRef<Path>,
-) -> Result<(), OBSCargoError> {
+) -> Result<Vec<VersionChange>, OBSCargoError> {
     info!("⏫ Updating dependencies before vendor");
     let update_options: Vec<OsString> = vec![
         "-vv".into(),
         "--manifest-path".into(),
         manifest_path.as_ref().into(),
     ];
+    let lockfile = manifest_path
+        .as_ref()
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join("Cargo.lock");
+    let before = locked_versions(&lockfile);
 
-    Ok({
-        cargo_command("update", &update_options, &prjdir).map_err(|e| {
-            error!(err = %e);
-            OBSCargoError::new(
-                OBSCargoErrorKind::VendorError,
-                "Unable to execute cargo".to_string(),
-            )
-        })?;
-        info!("⏫ Successfully ran cargo update");
-    })
+    cargo_command("update", &update_options, &prjdir, opts).map_err(|e| network_error(opts, &e))?;
+    info!("⏫ Successfully ran cargo update");
+
+    let (Some(before), Some(after)) = (before, locked_versions(&lockfile)) else {
+        debug!(
+            ?lockfile,
+            "No lockfile to compare before and after the update"
+        );
+        return Ok(Vec::new());
+    };
+    let changes = version_changes(&before, &after);
+    if changes.is_empty() {
+        info!("⏫ Dependencies are up to date, no crate changed version");
+    } else {
+        info!(
+            "⏫ Dependencies were updated; {} crates changed version",
+            changes.len()
+        );
+        for change in &changes {
+            info!(
+                crate_name = %change.name,
+                old_version = change.old.as_deref(),
+                new_version = change.new.as_deref(),
+                "⏫   {}",
+                change
+            );
+        }
+    }
+    Ok(changes)
 }
 
-pub fn generate_lockfile(manifest_path: impl AsRef<Path>) -> Result<(), OBSCargoError> {
+pub fn generate_lockfile(
+    opts: &Opts,
+    manifest_path: impl AsRef<Path>,
+) -> Result<(), OBSCargoError> {
     let lockfile_options: Vec<OsString> = vec![
         "-vv".into(),
         "--manifest-path".into(),
@@ -68,27 +131,71 @@ pub fn generate_lockfile(manifest_path: impl AsRef<Path>) -> Result<(), OBSCargo
     };
 
     Ok({
-        cargo_command("generate-lockfile", &lockfile_options, parent_path).map_err(|e| {
-            error!(err = %e);
-            OBSCargoError::new(
+        cargo_command("generate-lockfile", &lockfile_options, parent_path, opts).map_err(|e| {
+            cargo_error(
                 OBSCargoErrorKind::LockFileError,
-                "Unable to generate a lockfile".into(),
+                "Unable to generate a lockfile",
+                &e,
             )
         })?;
         info!("🔒 Successfully generated lockfile")
     })
 }
 
-pub fn vendor(
-    prjdir: impl AsRef<Path>,
-    cargo_config: impl AsRef<Path>,
-    manifest_path: impl AsRef<Path>,
+/// Fails if the lockfile next to `manifest_path` is missing or would need
+/// changes to match the manifest.
+pub fn verify_lockfile(opts: &Opts, manifest_path: impl AsRef<Path>) -> Result<(), OBSCargoError> {
+    let manifest_path = manifest_path.as_ref();
+    let metadata_options: Vec<OsString> = vec![
+        "--locked".into(),
+        "--format-version=1".into(),
+        "--manifest-path".into(),
+        manifest_path.into(),
+    ];
+    let curdir = manifest_path.parent().unwrap_or(Path::new("."));
+
+    cargo_command("metadata", &metadata_options, curdir, opts).map_err(|e| {
+        cargo_error(
+            OBSCargoErrorKind::VendorError,
+            &format!(
+                "lockfile for {} is missing or needs to be updated, but `--locked` was set",
+                manifest_path.display()
+            ),
+            &e,
+        )
+    })?;
+    info!("🔒 Lockfile for {} is up to date", manifest_path.display());
+    Ok(())
+}
+
+/// The platforms cargo-vendor-filterer is asked to vendor for. Empty if
+/// dependencies are not filtered.
+pub fn filter_platforms(opts: &Opts) -> Vec<String> {
+    if !opts.filter_platform.is_empty() {
+        opts.filter_platform.clone()
+    } else if opts.filter {
+        vec![
+            "*-unknown-linux-gnu".to_string(),
+            // Some crates compile their plugins to WASM, so we need those dependencies as well.
+            // Conservatively adding them everywhere, even if they are not needed everywhere.
+            // But the impact should be small.
+            "wasm32-wasi".to_string(),
+            "wasm32-unknown-unknown".to_string(),
+        ]
+    } else {
+        Vec::new()
+    }
+}
+
+/// The cargo subcommand vendoring `manifest_path` and its arguments. That is
+/// `vendor-filterer` if dependencies are filtered, otherwise `vendor`.
+fn vendor_args(
+    opts: &Opts,
+    manifest_path: &Path,
     extra_manifest_paths: &[impl AsRef<Path>],
-    filter: bool,
-    respect_lockfile: bool,
-) -> Result<(), OBSCargoError> {
-    let mut vendor_options: Vec<OsString> =
-        vec!["--manifest-path".into(), manifest_path.as_ref().into()];
+) -> (&'static str, Vec<OsString>) {
+    let respect_lockfile = opts.respect_lockfile;
+    let mut vendor_options: Vec<OsString> = vec!["--manifest-path".into(), manifest_path.into()];
 
     vendor_options.push("--versioned-dirs".into());
 
@@ -97,14 +204,25 @@ pub fn vendor(
         vendor_options.push(ex_path.as_ref().into());
     }
 
-    let cargo_subcommand = if filter {
-        info!("Filter set to true. Only vendoring crates for platforms *-unknown-linux-gnu and wasm32-*");
-        vendor_options.push("--platform=*-unknown-linux-gnu".into());
-        // Some crates compile their plugins to WASM, so we need those dependencies as well.
-        // Conservatively adding them everywhere, even if they are not needed everywhere.
-        // But the impact should be small.
-        vendor_options.push("--platform=wasm32-wasi".into());
-        vendor_options.push("--platform=wasm32-unknown-unknown".into());
+    let platforms = filter_platforms(opts);
+    let subcommand = if !platforms.is_empty() || opts.no_dev_deps {
+        if platforms.is_empty() {
+            debug!("Not filtering by platform");
+        } else if opts.filter_platform.is_empty() {
+            info!("Filter set to true. Only vendoring crates for platforms *-unknown-linux-gnu and wasm32-*");
+        } else {
+            info!(platforms = ?opts.filter_platform, "Only vendoring crates for the given platforms");
+        }
+        for platform in &platforms {
+            vendor_options.push(format!("--platform={}", platform).into());
+        }
+        if opts.no_dev_deps {
+            // Crates that are also a normal or build dependency are kept.
+            // The others are replaced by empty stubs, so the lockfile
+            // still resolves against the vendored sources.
+            info!("✂️ Not vendoring crates only needed as dev-dependencies");
+            vendor_options.push("--keep-dep-kinds=no-dev".into());
+        }
         // We are conservative here and vendor all possible features, even
         // if they are not used in the spec. But we can't know.
         // Maybe make this configurable?
@@ -121,23 +239,72 @@ pub fn vendor(
         // cargo-vendor-filterer doesn't support `-vv`
         vendor_options.push("-vv".into());
         // Enforce lock is up-to-date despite the fact we are regenerating the locks
-        if respect_lockfile {
+        if respect_lockfile || opts.is_locked() {
             // NOTE: Only vendor has the --locked option
             vendor_options.push("--locked".into());
         };
         "vendor"
     };
 
+    // Both cargo vendor and vendor-filterer vendor into `vendor/` by default.
+    if opts.vendor_dir_name != "vendor" {
+        vendor_options.push(opts.vendor_dir_name.clone().into());
+    }
+    (subcommand, vendor_options)
+}
+
+pub fn vendor(
+    opts: &Opts,
+    prjdir: impl AsRef<Path>,
+    cargo_config: impl AsRef<Path>,
+    manifest_path: impl AsRef<Path>,
+    extra_manifest_paths: &[impl AsRef<Path>],
+) -> Result<(), OBSCargoError> {
+    let (cargo_subcommand, vendor_options) =
+        vendor_args(opts, manifest_path.as_ref(), extra_manifest_paths);
     debug!(?vendor_options);
 
-    let cargo_vendor_output =
-        cargo_command(cargo_subcommand, &vendor_options, &prjdir).map_err(|e| {
-            error!(err = %e);
-            OBSCargoError::new(
+    if opts.is_offline() {
+        info!("✈️ Vendoring offline. Only crates from the local cargo cache are used.");
+    }
+
+    // Read before vendoring so a broken fragment does not waste a vendor run.
+    let fragments = read_config_fragments(&opts.config_fragment)?;
+    // A config shipped with the sources, e.g. from an earlier vendor run.
+    let existing_config = read_existing_config(cargo_config.as_ref());
+
+    let vendor_dir = prjdir.as_ref().join(&opts.vendor_dir_name);
+    let cargo_vendor_output = progress::watch_dir("Vendoring", &vendor_dir, || {
+        cargo_command(cargo_subcommand, &vendor_options, &prjdir, opts)
+    })
+    .map_err(|e| network_error(opts, &e))?;
+
+    if opts.minimize {
+        minimize::minimize_vendor_dir(&vendor_dir).map_err(|err| {
+            error!(?err, "Failed to minimize vendored crates");
+            OBSCargoError::new_with_source(
                 OBSCargoErrorKind::VendorError,
-                "Unable to execute cargo".to_string(),
+                "failed to minimize vendored crates".to_string(),
+                err,
             )
         })?;
+    }
+
+    if !opts.exclude_crate.is_empty() {
+        exclude_crates(&vendor_dir, &opts.exclude_crate)?;
+    }
+
+    let lockfiles: Vec<PathBuf> = std::iter::once(manifest_path.as_ref())
+        .chain(extra_manifest_paths.iter().map(|p| p.as_ref()))
+        .filter_map(|p| p.parent().map(|parent| parent.join("Cargo.lock")))
+        .filter(|p| p.exists())
+        .collect();
+    let cargo_vendor_output = add_missing_git_sources(&cargo_vendor_output, &lockfiles);
+    let cargo_vendor_output = match &opts.registry_mirror {
+        Some(mirror) => use_registry_mirror(&cargo_vendor_output, mirror)?,
+        None => cargo_vendor_output,
+    };
+    let cargo_vendor_output = merge_configs(&cargo_vendor_output, existing_config, fragments)?;
 
     if let Some(p_path) = cargo_config.as_ref().parent() {
         fs::create_dir_all(p_path).map_err(|err| {
@@ -165,16 +332,469 @@ pub fn vendor(
                 OBSCargoErrorKind::VendorError,
                 "failed to write to file for cargo config".to_string(),
             )
+        })?;
+
+    if opts.print_config {
+        // Locked so that configs of parallel runs are not interleaved.
+        io::stdout()
+            .lock()
+            .write_all(cargo_vendor_output.as_bytes())
+            .map_err(|err| {
+                error!(?err, "Failed to print cargo config");
+                OBSCargoError::new_with_source(
+                    OBSCargoErrorKind::VendorError,
+                    "failed to print cargo config".to_string(),
+                    err,
+                )
+            })?;
+    }
+    Ok(())
+}
+
+/// Reads the `config-fragment` files into one table, later files winning
+/// over earlier ones. `None` if there are none.
+fn read_config_fragments(paths: &[PathBuf]) -> Result<Option<toml::Table>, OBSCargoError> {
+    if paths.is_empty() {
+        return Ok(None);
+    }
+    let mut merged = toml::Table::new();
+    for path in paths {
+        let data = fs::read_to_string(path).map_err(|err| {
+            error!(?err, ?path, "Unable to read cargo config fragment");
+            OBSCargoError::new_with_source(
+                OBSCargoErrorKind::VendorError,
+                format!("unable to read cargo config fragment {}", path.display()),
+                err,
+            )
+        })?;
+        let fragment = toml::from_str::<toml::Table>(&data).map_err(|err| {
+            error!(?err, ?path, "Cargo config fragment is not valid TOML");
+            OBSCargoError::new_with_source(
+                OBSCargoErrorKind::VendorError,
+                format!(
+                    "cargo config fragment {} is not valid TOML: {}",
+                    path.display(),
+                    err
+                ),
+                err,
+            )
+        })?;
+        debug!(?path, "Read cargo config fragment");
+        merge_toml(&mut merged, fragment);
+    }
+    Ok(Some(merged))
+}
+
+/// Merges `overlay` into `base`. Tables are merged key by key, any other
+/// value in `overlay` replaces the one in `base`.
+fn merge_toml(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_toml(base_table, overlay_table)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Reads the cargo config that is already at `cargo_config`, returning its
+/// text and parsed contents. An unparsable config is replaced as before.
+fn read_existing_config(cargo_config: &Path) -> Option<(String, toml::Table)> {
+    let data = fs::read_to_string(cargo_config).ok()?;
+    match toml::from_str::<toml::Table>(&data) {
+        Ok(table) => {
+            debug!(?cargo_config, "Found existing cargo config");
+            Some((data, table))
+        }
+        Err(err) => {
+            warn!(
+                ?err,
+                ?cargo_config,
+                "⚠️ Unable to parse existing cargo config, replacing it"
+            );
+            None
+        }
+    }
+}
+
+/// Combines the generated cargo config in `config` with an existing config
+/// and the `fragments`. The generated source replacement wins on conflicts,
+/// so the vendored sources are always used. If the existing config already
+/// has everything, its text is kept as is, so re-vendoring an unchanged
+/// project writes the same config again.
+fn merge_configs(
+    config: &str,
+    existing: Option<(String, toml::Table)>,
+    fragments: Option<toml::Table>,
+) -> Result<String, OBSCargoError> {
+    if existing.is_none() && fragments.is_none() {
+        return Ok(config.to_string());
+    }
+    let generated = toml::from_str::<toml::Table>(config).map_err(|err| {
+        error!(?err, "Unable to parse generated cargo config");
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorError,
+            "unable to parse generated cargo config to merge it".to_string(),
+            err,
+        )
+    })?;
+    let mut merged = existing
+        .as_ref()
+        .map(|(_, table)| table.clone())
+        .unwrap_or_default();
+    if let Some(fragments) = fragments {
+        merge_toml(&mut merged, fragments);
+        info!("🧩 Merged cargo config fragments into the cargo config");
+    }
+    merge_toml(&mut merged, generated);
+    if let Some((text, table)) = existing {
+        if merged == table {
+            info!("♻️ Existing cargo config is up to date, keeping it");
+            return Ok(text);
+        }
+        info!("♻️ Merged the source replacement into the existing cargo config");
+    }
+    toml::to_string(&merged).map_err(|err| {
+        error!(?err, "Unable to write merged cargo config");
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorError,
+            "unable to write merged cargo config".to_string(),
+            err,
+        )
+    })
+}
+
+/// A git source as recorded in `Cargo.lock`, e.g.
+/// `git+https://github.com/foo/bar?rev=abc#<commit>`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct GitSource {
+    /// The source without the locked commit. This is the name cargo uses
+    /// for the `[source."..."]` table.
+    key: String,
+    url: String,
+    /// One of `rev`, `branch` or `tag` with its value.
+    reference: Option<(String, String)>,
+}
+
+impl GitSource {
+    fn parse(source: &str) -> Option<Self> {
+        let key = source.split('#').next()?;
+        let url_and_query = key.strip_prefix("git+")?;
+        let (url, query) = match url_and_query.split_once('?') {
+            Some((url, query)) => (url, Some(query)),
+            None => (url_and_query, None),
+        };
+        let reference = query.and_then(|q| {
+            q.split('&').find_map(|pair| match pair.split_once('=') {
+                Some((kind @ ("rev" | "branch" | "tag"), value)) => {
+                    Some((kind.to_string(), value.to_string()))
+                }
+                _ => None,
+            })
+        });
+        Some(Self {
+            key: key.to_string(),
+            url: url.to_string(),
+            reference,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlLockfile {
+    #[serde(default)]
+    package: Vec<TomlLockedPackage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlLockedPackage {
+    name: String,
+    version: String,
+    source: Option<String>,
+}
+
+/// A crate whose locked version changed when updating the dependencies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionChange {
+    pub name: String,
+    /// `None` if the crate was added to the lockfile.
+    pub old: Option<String>,
+    /// `None` if the crate was removed from the lockfile.
+    pub new: Option<String>,
+}
+
+impl std::fmt::Display for VersionChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.old, &self.new) {
+            (Some(old), Some(new)) => write!(f, "{} {} -> {}", self.name, old, new),
+            (None, Some(new)) => write!(f, "{} {} (added)", self.name, new),
+            (Some(old), None) => write!(f, "{} {} (removed)", self.name, old),
+            (None, None) => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// The locked versions of each crate in `lockfile`, or `None` if there is no
+/// readable lockfile.
+fn locked_versions(lockfile: &Path) -> Option<BTreeMap<String, Vec<String>>> {
+    let data = fs::read_to_string(lockfile).ok()?;
+    let lockfile = toml::from_str::<TomlLockfile>(&data)
+        .inspect_err(|err| warn!(?err, "⚠️ Unable to parse lockfile"))
+        .ok()?;
+    let mut versions: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for package in lockfile.package {
+        versions
+            .entry(package.name)
+            .or_default()
+            .push(package.version);
+    }
+    Some(versions)
+}
+
+/// Compares the locked versions before and after an update. A crate locked
+/// in several versions pairs its removed versions with its added ones in
+/// order, the rest count as added or removed.
+fn version_changes(
+    before: &BTreeMap<String, Vec<String>>,
+    after: &BTreeMap<String, Vec<String>>,
+) -> Vec<VersionChange> {
+    let mut changes = Vec::new();
+    let names: std::collections::BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    for name in names {
+        let old_versions = before.get(name).map(Vec::as_slice).unwrap_or_default();
+        let new_versions = after.get(name).map(Vec::as_slice).unwrap_or_default();
+        let mut removed = old_versions
+            .iter()
+            .filter(|v| !new_versions.contains(v))
+            .cloned();
+        let mut added = new_versions
+            .iter()
+            .filter(|v| !old_versions.contains(v))
+            .cloned();
+        loop {
+            let (old, new) = (removed.next(), added.next());
+            if old.is_none() && new.is_none() {
+                break;
+            }
+            changes.push(VersionChange {
+                name: name.clone(),
+                old,
+                new,
+            });
+        }
+    }
+    changes
+}
+
+/// Collects the git sources of all packages in the given lockfiles. Path
+/// dependencies have no source and are left alone.
+fn git_sources(lockfiles: &[impl AsRef<Path>]) -> Vec<GitSource> {
+    let mut sources: Vec<GitSource> = lockfiles
+        .iter()
+        .filter_map(|lockfile| {
+            fs::read_to_string(lockfile.as_ref())
+                .inspect_err(
+                    |err| warn!(?err, lockfile = ?lockfile.as_ref(), "⚠️ Unable to read lockfile"),
+                )
+                .ok()
+        })
+        .filter_map(|data| {
+            toml::from_str::<TomlLockfile>(&data)
+                .inspect_err(|err| warn!(?err, "⚠️ Unable to parse lockfile"))
+                .ok()
         })
+        .flat_map(|lockfile| lockfile.package)
+        .filter_map(|package| package.source.as_deref().and_then(GitSource::parse))
+        .collect();
+    sources.sort();
+    sources.dedup();
+    sources
 }
 
+/// Makes sure every git source from the lockfiles is replaced by the vendored
+/// sources in the cargo config. Stanzas that cargo did not emit are appended.
+fn add_missing_git_sources(config: &str, lockfiles: &[impl AsRef<Path>]) -> String {
+    let sources = git_sources(lockfiles);
+    if sources.is_empty() {
+        return config.to_string();
+    }
+    let Ok(parsed) = toml::from_str::<toml::Value>(config) else {
+        warn!("⚠️ Unable to parse generated cargo config. Not checking git sources.");
+        return config.to_string();
+    };
+    let configured = parsed.get("source").and_then(|s| s.as_table());
+    let replace_with = configured
+        .and_then(|table| {
+            table
+                .iter()
+                .find(|(_, v)| v.get("directory").is_some())
+                .map(|(name, _)| name.clone())
+        })
+        .unwrap_or_else(|| "vendored-sources".to_string());
+
+    let mut config = config.to_string();
+    for source in sources {
+        if configured.is_some_and(|table| table.contains_key(&source.key)) {
+            trace!(key = source.key, "Git source already in cargo config");
+            continue;
+        }
+        info!(
+            "🌿 Adding missing git source {} to cargo config",
+            source.key
+        );
+        if !config.ends_with('\n') && !config.is_empty() {
+            config.push('\n');
+        }
+        config.push_str(&format!("\n[source.{:?}]\n", source.key));
+        config.push_str(&format!("git = {:?}\n", source.url));
+        if let Some((kind, value)) = &source.reference {
+            config.push_str(&format!("{} = {:?}\n", kind, value));
+        }
+        config.push_str(&format!("replace-with = {:?}\n", replace_with));
+    }
+    config
+}
+
+/// Points crates.io at the registry `mirror` instead of the vendored sources.
+/// Git sources still use the vendored sources.
+fn use_registry_mirror(config: &str, mirror: &str) -> Result<String, OBSCargoError> {
+    let mut table = toml::from_str::<toml::Table>(config).map_err(|err| {
+        error!(?err, "Unable to parse generated cargo config");
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorError,
+            "unable to parse generated cargo config to add the registry mirror".to_string(),
+            err,
+        )
+    })?;
+    let mut crates_io = toml::Table::new();
+    crates_io.insert(
+        "replace-with".to_string(),
+        toml::Value::String(REGISTRY_MIRROR_SOURCE.to_string()),
+    );
+    let mut registry = toml::Table::new();
+    registry.insert(
+        "registry".to_string(),
+        toml::Value::String(mirror.to_string()),
+    );
+    let mut sources = toml::Table::new();
+    sources.insert("crates-io".to_string(), toml::Value::Table(crates_io));
+    sources.insert(
+        REGISTRY_MIRROR_SOURCE.to_string(),
+        toml::Value::Table(registry),
+    );
+    let mut overlay = toml::Table::new();
+    overlay.insert("source".to_string(), toml::Value::Table(sources));
+    merge_toml(&mut table, overlay);
+    info!("🪞 Cargo config points crates.io at the mirror {}", mirror);
+    toml::to_string(&table).map_err(|err| {
+        error!(?err, "Unable to write cargo config with registry mirror");
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorError,
+            "unable to write cargo config with the registry mirror".to_string(),
+            err,
+        )
+    })
+}
+
+/// What a vendor run produced.
+#[derive(Debug)]
+pub struct VendorOutput {
+    /// The vendored tarball below `outdir`. `None` if the project has no
+    /// dependencies and nothing was vendored.
+    pub tarball: Option<PathBuf>,
+    /// Path of the generated cargo config inside the tarball, relative to
+    /// the project root, e.g. `.cargo/config.toml`.
+    pub cargo_config: Option<PathBuf>,
+    /// `<tarball>.sha256` with the checksum of the tarball, if requested.
+    pub checksum: Option<PathBuf>,
+    /// `<tarball>.crates` listing the vendored crates, if requested.
+    pub crate_list: Option<PathBuf>,
+    /// Advisories found by the audit. Since the audit fails on anything
+    /// not accepted, these are all waived ones.
+    pub audit_findings: Vec<AuditFinding>,
+    /// Size of what was vendored. `None` if nothing was vendored.
+    pub stats: Option<VendorStats>,
+    /// Name and version of the project, `None` if its manifest could not be
+    /// read.
+    pub project: Option<ProjectInfo>,
+}
+
+/// How many crates were vendored and how big they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VendorStats {
+    pub crates: u64,
+    /// Size of all files in the vendor directory.
+    pub uncompressed_bytes: u64,
+    /// Size of the tarball.
+    pub compressed_bytes: u64,
+}
+
+/// Counts the crates in `vendor_dir` and sums up their sizes and the size
+/// of `tarball`, then logs a one-line summary.
+pub fn vendor_stats(
+    opts: &Opts,
+    vendor_dir: &Path,
+    tarball: &Path,
+) -> Result<VendorStats, OBSCargoError> {
+    let io_error = |err: io::Error| {
+        error!(?err, "Failed to measure vendored crates");
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorError,
+            "failed to measure vendored crates".to_string(),
+            err,
+        )
+    };
+    let crates = fs::read_dir(vendor_dir)
+        .map_err(io_error)?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().join("Cargo.toml").is_file())
+        .count() as u64;
+    let mut uncompressed_bytes = 0;
+    for entry in walkdir::WalkDir::new(vendor_dir) {
+        let entry = entry.map_err(|err| io_error(err.into()))?;
+        if entry.file_type().is_file() {
+            uncompressed_bytes += entry.metadata().map_err(|err| io_error(err.into()))?.len();
+        }
+    }
+    let compressed_bytes = fs::metadata(tarball).map_err(io_error)?.len();
+    info!(
+        crates,
+        uncompressed_bytes,
+        compressed_bytes,
+        "📊 Vendored {} crates, {} uncompressed, {} compressed ({})",
+        crates,
+        progress::human_bytes(uncompressed_bytes),
+        progress::human_bytes(compressed_bytes),
+        opts.output_compression().tar_extension()
+    );
+    Ok(VendorStats {
+        crates,
+        uncompressed_bytes,
+        compressed_bytes,
+    })
+}
+
+/// File name of the vendor tarball: `vendor.tar.zst` without a tag and
+/// `vendor-<tag>.tar.zst` with one, so tagged runs into the same `outdir`
+/// do not replace each other's tarball.
+pub fn tarball_name(tag: Option<&str>, compression: Compression) -> String {
+    // Not using `Path::set_extension` as it would cut tags like `1.2` short.
+    match tag {
+        Some(t) => format!("vendor-{}.{}", t, compression.tar_extension()),
+        None => format!("vendor.{}", compression.tar_extension()),
+    }
+}
+
+/// Compresses `paths_to_archive` into the vendor tarball and returns its path.
 pub fn compress(
-    outpath: impl AsRef<Path>,
+    opts: &Opts,
     prjdir: impl AsRef<Path>,
-    paths_to_archive: &[impl AsRef<Path>],
-    compression: &Compression,
     tag: Option<&str>,
-) -> Result<(), OBSCargoError> {
+    paths_to_archive: &[impl AsRef<Path>],
+) -> Result<PathBuf, OBSCargoError> {
     info!("📦 Archiving vendored dependencies...");
 
     // RATIONALE: We copy Cargo.lock by default, updated or not updated
@@ -190,116 +810,317 @@ pub fn compress(
     // NOTE: 3. If they are not members, we slap that file into their own compressed vendored
     //          tarball
 
-    let tar_name = match tag {
-        Some(t) => format!("vendor-{}", t),
-        None => "vendor".to_string(),
+    let compression = &opts.output_compression();
+    let options = compress::ArchiveOptions {
+        prefix: opts.prefix.as_deref(),
+        level: opts.compression_level,
+        threads: opts.compression_threads,
+        reproducible: opts.reproducible,
+        max_size: opts.max_archive_size,
+        dedup: opts.dedup,
     };
+    if let Some(prefix) = options.prefix {
+        debug!(?prefix, "Storing tarball entries under prefix");
+    }
+    if !options.reproducible {
+        warn!("⚠️ Reproducible tarballs disabled. Timestamps and ownership will be taken from the filesystem.");
+    }
 
-    let mut vendor_out = outpath.as_ref().join(tar_name);
-    Ok({
+    let vendor_out = opts.outdir.join(tarball_name(tag, *compression));
+    {
         match compression {
             Compression::Gz => {
-                vendor_out.set_extension("tar.gz");
                 if vendor_out.exists() {
                     warn!(
                         replacing = ?vendor_out,
                         "🔦 Compressed tarball for vendor exists AND will be replaced."
                     );
                 }
-                compress::targz(&vendor_out, &prjdir, paths_to_archive, true).map_err(|err| {
-                    error!(?err, "gz compression failed");
-                    OBSCargoError::new(
-                        OBSCargoErrorKind::VendorCompressionFailed,
-                        "gz compression failed".to_string(),
-                    )
-                })?;
+                compress::targz(&vendor_out, &prjdir, paths_to_archive, options)
+                    .map_err(|err| compression_error(err, "gz compression failed", &vendor_out))?;
                 debug!(
                     "Compressed and archived to {}",
                     vendor_out.to_string_lossy()
                 );
             }
             Compression::Xz => {
-                vendor_out.set_extension("tar.xz");
                 if vendor_out.exists() {
                     warn!(
                         replacing = ?vendor_out,
                         "🔦 Compressed tarball for vendor exists AND will be replaced."
                     );
                 }
-                compress::tarxz(&vendor_out, &prjdir, paths_to_archive, true).map_err(|err| {
-                    error!(?err, "xz compression failed");
-                    OBSCargoError::new(
-                        OBSCargoErrorKind::VendorCompressionFailed,
-                        "xz compression failed".to_string(),
-                    )
-                })?;
+                compress::tarxz(&vendor_out, &prjdir, paths_to_archive, options)
+                    .map_err(|err| compression_error(err, "xz compression failed", &vendor_out))?;
                 debug!(
                     "Compressed and archived to {}",
                     vendor_out.to_string_lossy()
                 );
             }
             Compression::Zst => {
-                vendor_out.set_extension("tar.zst");
                 if vendor_out.exists() {
                     warn!(
                         replacing = ?vendor_out,
                         "🔦 Compressed tarball for vendor exists AND will be replaced."
                     );
                 }
-                compress::tarzst(&vendor_out, &prjdir, paths_to_archive, true).map_err(|err| {
-                    error!(?err, "zst compression failed");
-                    OBSCargoError::new(
-                        OBSCargoErrorKind::VendorCompressionFailed,
-                        "zst compression failed".to_string(),
-                    )
-                })?;
+                compress::tarzst(&vendor_out, &prjdir, paths_to_archive, options)
+                    .map_err(|err| compression_error(err, "zst compression failed", &vendor_out))?;
                 debug!(
                     "Compressed and archived to {}",
                     vendor_out.to_string_lossy()
                 );
             }
             Compression::Bz2 => {
-                vendor_out.set_extension("tar.bz2");
                 if vendor_out.exists() {
                     warn!(
                         replacing = ?vendor_out,
                         "🔦 Compressed tarball for vendor exists AND will be replaced."
                     );
                 }
-                compress::tarbz2(&vendor_out, &prjdir, paths_to_archive, true).map_err(|err| {
-                    error!(?err, "bz2 compression failed");
-                    OBSCargoError::new(
-                        OBSCargoErrorKind::VendorCompressionFailed,
-                        "bz2 compression failed".to_string(),
-                    )
-                })?;
+                compress::tarbz2(&vendor_out, &prjdir, paths_to_archive, options)
+                    .map_err(|err| compression_error(err, "bz2 compression failed", &vendor_out))?;
                 debug!(
                     "Compressed and archived to {}",
                     vendor_out.to_string_lossy()
                 );
             }
             Compression::Not => {
-                vendor_out.set_extension("tar");
                 if vendor_out.exists() {
                     warn!(
                         replacing = ?vendor_out,
                         "🔦 Uncompressed vanilla tarball for vendor exists AND will be replaced."
                     );
                 }
-                compress::tarbz2(&vendor_out, &prjdir, paths_to_archive, true).map_err(|err| {
-                    error!(?err, "bz2 compression failed");
-                    OBSCargoError::new(
-                        OBSCargoErrorKind::VendorCompressionFailed,
-                        "archiving vendor source failed".to_string(),
-                    )
-                })?;
+                compress::vanilla(&vendor_out, &prjdir, paths_to_archive, options).map_err(
+                    |err| compression_error(err, "archiving vendor source failed", &vendor_out),
+                )?;
                 debug!("Archived to {}", vendor_out.to_string_lossy());
             }
         }
         debug!("Finished creating {} compressed tarball", compression);
+    }
+    verify_tarball(*compression, &vendor_out, paths_to_archive)?;
+    Ok(vendor_out)
+}
+
+/// Turns a failed `compress::*` call into an error. An archive that outgrew
+/// `max-archive-size` is removed and gets its own message, anything else is
+/// reported as `message`.
+fn compression_error(err: io::Error, message: &str, tarball: &Path) -> OBSCargoError {
+    if err.kind() == io::ErrorKind::FileTooLarge {
+        error!(?err, ?tarball, "🛑 Vendor tarball is too large");
+        if let Err(remove_err) = fs::remove_file(tarball) {
+            warn!(
+                ?remove_err,
+                ?tarball,
+                "⚠️ Unable to remove oversized tarball"
+            );
+        }
+        return OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorCompressionFailed,
+            format!(
+                "vendor tarball {} was aborted: {}. Raise `max-archive-size` or vendor fewer crates, e.g. with `filter`",
+                tarball.display(),
+                err
+            ),
+            err,
+        );
+    }
+    error!(?err, "{}", message);
+    OBSCargoError::new_with_source(
+        OBSCargoErrorKind::VendorCompressionFailed,
+        message.to_string(),
+        err,
+    )
+}
+
+/// `<tarball><suffix>` next to the tarball.
+fn sidecar_path(tarball: &Path, suffix: &str) -> PathBuf {
+    let mut name = tarball.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn write_sidecar(path: &Path, contents: &str) -> Result<(), OBSCargoError> {
+    fs::write(path, contents).map_err(|err| {
+        error!(
+            ?err,
+            ?path,
+            "Failed to write file next to the vendor tarball"
+        );
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorError,
+            format!("failed to write {}", path.display()),
+            err,
+        )
     })
 }
 
+/// Writes the SHA-256 of the tarball to `<tarball>.sha256` in the format of
+/// `sha256sum`, so it can be checked with `sha256sum -c`.
+pub fn write_checksum(tarball: &Path) -> Result<PathBuf, OBSCargoError> {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    fs::File::open(tarball)
+        .and_then(|mut file| io::copy(&mut file, &mut hasher))
+        .map_err(|err| {
+            error!(?err, ?tarball, "Failed to read vendor tarball for checksum");
+            OBSCargoError::new_with_source(
+                OBSCargoErrorKind::VendorError,
+                format!("failed to read {} for its checksum", tarball.display()),
+                err,
+            )
+        })?;
+    let digest: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let name = tarball
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let checksum = sidecar_path(tarball, ".sha256");
+    write_sidecar(&checksum, &format!("{}  {}\n", digest, name))?;
+    info!("🔏 Wrote checksum {} to {}", digest, checksum.display());
+    Ok(checksum)
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlVendoredManifest {
+    package: TomlVendoredPackage,
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlVendoredPackage {
+    name: String,
+    version: String,
+}
+
+/// Removes every version of the crates named in `exclude` from `vendor_dir`.
+fn exclude_crates(vendor_dir: &Path, exclude: &[String]) -> Result<(), OBSCargoError> {
+    let entries = fs::read_dir(vendor_dir).map_err(|err| {
+        error!(?err, ?vendor_dir, "Failed to read vendor directory");
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorError,
+            format!("failed to read vendor directory {}", vendor_dir.display()),
+            err,
+        )
+    })?;
+    let mut excluded = Vec::new();
+    for crate_dir in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+        let manifest = crate_dir.join("Cargo.toml");
+        let Some(package) = fs::read_to_string(&manifest)
+            .ok()
+            .and_then(|data| toml::from_str::<TomlVendoredManifest>(&data).ok())
+            .map(|manifest| manifest.package)
+        else {
+            continue;
+        };
+        if !exclude.contains(&package.name) {
+            continue;
+        }
+        fs::remove_dir_all(&crate_dir).map_err(|err| {
+            error!(?err, ?crate_dir, "Failed to remove excluded crate");
+            OBSCargoError::new_with_source(
+                OBSCargoErrorKind::VendorError,
+                format!("failed to remove excluded crate {}", crate_dir.display()),
+                err,
+            )
+        })?;
+        info!(
+            crate_name = %package.name,
+            crate_version = %package.version,
+            "🚫 Excluded {} {} from the vendored crates",
+            package.name,
+            package.version
+        );
+        excluded.push(package.name);
+    }
+    for name in exclude.iter().filter(|name| !excluded.contains(name)) {
+        warn!(crate_name = %name, "⚠️ Crate {} is not vendored, nothing to exclude", name);
+    }
+    if !excluded.is_empty() {
+        warn!("⚠️ Excluded crates are not in the vendor tarball, the build has to provide them itself");
+    }
+    Ok(())
+}
+
+/// Writes the name and version of every crate in `vendor_dir`, one per line
+/// and sorted, to `<tarball>.crates`.
+pub fn write_crate_list(tarball: &Path, vendor_dir: &Path) -> Result<PathBuf, OBSCargoError> {
+    let entries = fs::read_dir(vendor_dir).map_err(|err| {
+        error!(?err, ?vendor_dir, "Failed to read vendor directory");
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorError,
+            format!("failed to read vendor directory {}", vendor_dir.display()),
+            err,
+        )
+    })?;
+    let mut crates: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let manifest = entry.path().join("Cargo.toml");
+            let data = fs::read_to_string(&manifest).ok()?;
+            toml::from_str::<TomlVendoredManifest>(&data)
+                .inspect_err(|err| warn!(?err, ?manifest, "⚠️ Unable to parse vendored manifest"))
+                .ok()
+        })
+        .map(|manifest| format!("{} {}", manifest.package.name, manifest.package.version))
+        .collect();
+    crates.sort();
+    let crate_list = sidecar_path(tarball, ".crates");
+    let mut contents = crates.join("\n");
+    contents.push('\n');
+    write_sidecar(&crate_list, &contents)?;
+    info!(
+        "📜 Wrote list of {} vendored crates to {}",
+        crates.len(),
+        crate_list.display()
+    );
+    Ok(crate_list)
+}
+
+/// Reads the tarball back and checks it has as many entries as we put in,
+/// so a truncated or corrupt tarball fails the service instead of the build.
+fn verify_tarball(
+    compression: Compression,
+    tarball: &Path,
+    paths_to_archive: &[impl AsRef<Path>],
+) -> Result<(), OBSCargoError> {
+    let expected = compress::count_archive_entries(paths_to_archive).map_err(|err| {
+        error!(?err, "Unable to count the archived files");
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorCompressionFailed,
+            "unable to count the archived files".to_string(),
+            err,
+        )
+    })?;
+    let found = decompress::count_entries(compression, tarball).map_err(|err| {
+        error!(?err, ?tarball, "🛑 Vendor tarball is corrupt");
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorCompressionFailed,
+            format!("vendor tarball {} is corrupt: {}", tarball.display(), err),
+            err,
+        )
+    })?;
+    if found != expected {
+        error!(found, expected, ?tarball, "🛑 Vendor tarball is incomplete");
+        return Err(OBSCargoError::new(
+            OBSCargoErrorKind::VendorCompressionFailed,
+            format!(
+                "vendor tarball {} has {} entries but {} were archived",
+                tarball.display(),
+                found,
+                expected
+            ),
+        ));
+    }
+    debug!(found, "Verified vendor tarball");
+    Ok(())
+}
+
 pub fn is_workspace(src: &Path) -> Result<bool, OBSCargoError> {
     if let Ok(manifest) = fs::read_to_string(src) {
         if let Ok(manifest_data) = toml::from_str::<toml::Value>(&manifest) {
@@ -319,6 +1140,164 @@ pub fn is_workspace(src: &Path) -> Result<bool, OBSCargoError> {
     ))
 }
 
+/// Resolves the `members` of the workspace defined in `root_manifest` to the
+/// manifest paths of the member crates. Like cargo, crates in or below a
+/// directory listed in `exclude` are not members. `default-members` needs
+/// no handling, cargo requires them to be members anyway.
+pub fn workspace_members(root_manifest: &Path) -> Result<Vec<PathBuf>, OBSCargoError> {
+    let manifest = fs::read_to_string(root_manifest).map_err(|err| {
+        error!(?err, "Failed to read workspace manifest");
+        OBSCargoError::new(
+            OBSCargoErrorKind::VendorError,
+            format!(
+                "failed to read workspace manifest at path {}",
+                root_manifest.to_string_lossy()
+            ),
+        )
+    })?;
+    let manifest_data = toml::from_str::<toml::Value>(&manifest).map_err(|err| {
+        error!(?err, "Failed to deserialize TOML manifest file");
+        OBSCargoError::new(
+            OBSCargoErrorKind::VendorError,
+            "Failed to deserialize TOML manifest file".to_string(),
+        )
+    })?;
+    let Some(root_dir) = root_manifest.parent() else {
+        return Ok(Vec::new());
+    };
+
+    let patterns = manifest_data
+        .get("workspace")
+        .and_then(|ws| ws.get("members"))
+        .and_then(|members| members.as_array())
+        .map(|members| {
+            members
+                .iter()
+                .filter_map(|m| m.as_str())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let excluded: Vec<PathBuf> = manifest_data
+        .get("workspace")
+        .and_then(|ws| ws.get("exclude"))
+        .and_then(|exclude| exclude.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.as_str())
+        .map(|e| root_dir.join(e))
+        .collect();
+
+    let mut members = Vec::new();
+    for pattern in patterns {
+        let full_pattern = root_dir.join(pattern);
+        let paths = glob::glob(&full_pattern.to_string_lossy()).map_err(|err| {
+            error!(?err, pattern, "Invalid workspace member glob");
+            OBSCargoError::new(
+                OBSCargoErrorKind::VendorError,
+                format!("invalid workspace member pattern `{}`", pattern),
+            )
+        })?;
+        for member_dir in paths.flatten() {
+            if excluded.iter().any(|e| member_dir.starts_with(e)) {
+                debug!(?member_dir, "Excluded from the workspace");
+                continue;
+            }
+            let member_manifest = member_dir.join("Cargo.toml");
+            if member_manifest.is_file() {
+                members.push(member_manifest);
+            }
+        }
+    }
+    debug!(?members, "Workspace members");
+    Ok(members)
+}
+
+/// The project a vendor run was for, from its top-level manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProjectInfo {
+    /// A manifest with a `[package]`, also if it is the root of a workspace.
+    /// `version` is `None` if the manifest does not set one.
+    Package {
+        name: String,
+        version: Option<String>,
+    },
+    /// A virtual workspace, with the package names of its members.
+    Workspace { members: Vec<String> },
+}
+
+impl std::fmt::Display for ProjectInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProjectInfo::Package {
+                name,
+                version: Some(version),
+            } => write!(f, "{} {}", name, version),
+            ProjectInfo::Package {
+                name,
+                version: None,
+            } => write!(f, "{}", name),
+            ProjectInfo::Workspace { members } => {
+                write!(f, "workspace of {}", members.join(", "))
+            }
+        }
+    }
+}
+
+fn read_manifest_value(manifest: &Path) -> Result<toml::Value, OBSCargoError> {
+    let data = fs::read_to_string(manifest).map_err(|err| {
+        error!(?err, ?manifest, "Failed to read manifest");
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorError,
+            format!("failed to read manifest {}", manifest.display()),
+            err,
+        )
+    })?;
+    toml::from_str::<toml::Value>(&data).map_err(|err| {
+        error!(?err, ?manifest, "Failed to deserialize TOML manifest file");
+        OBSCargoError::new_with_source(
+            OBSCargoErrorKind::VendorError,
+            format!("failed to parse manifest {}", manifest.display()),
+            err,
+        )
+    })
+}
+
+/// Reads the package name and version from `manifest`, or the member names
+/// if it is a virtual workspace. A version inherited with
+/// `version.workspace = true` is taken from `[workspace.package]`.
+pub fn project_info(manifest: &Path) -> Result<ProjectInfo, OBSCargoError> {
+    let data = read_manifest_value(manifest)?;
+    let package_name = |data: &toml::Value| {
+        data.get("package")
+            .and_then(|p| p.get("name"))
+            .and_then(|n| n.as_str())
+            .map(str::to_string)
+    };
+    if let Some(name) = package_name(&data) {
+        let version = match data.get("package").and_then(|p| p.get("version")) {
+            Some(toml::Value::String(version)) => Some(version.clone()),
+            Some(toml::Value::Table(inherited))
+                if inherited.get("workspace").and_then(|w| w.as_bool()) == Some(true) =>
+            {
+                data.get("workspace")
+                    .and_then(|ws| ws.get("package"))
+                    .and_then(|p| p.get("version"))
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string)
+            }
+            _ => None,
+        };
+        return Ok(ProjectInfo::Package { name, version });
+    }
+    let mut members = Vec::new();
+    for member in workspace_members(manifest)? {
+        members.extend(package_name(&read_manifest_value(&member)?));
+    }
+    members.sort();
+    Ok(ProjectInfo::Workspace { members })
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 struct TomlManifest {
@@ -364,3 +1343,660 @@ pub fn has_dependencies(src: &Path) -> Result<bool, OBSCargoError> {
         ),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    /// A project with a vendor directory and the paths `compress` would archive.
+    fn vendored() -> (tempfile::TempDir, PathBuf, Vec<PathBuf>) {
+        let dir = tempfile::tempdir().unwrap();
+        let prjdir = dir.path().join("project");
+        for krate in ["a", "b"] {
+            let crate_dir = prjdir.join("vendor").join(krate);
+            fs::create_dir_all(&crate_dir).unwrap();
+            for i in 0..20 {
+                fs::write(
+                    crate_dir.join(format!("f{}.rs", i)),
+                    i.to_string().repeat(2000),
+                )
+                .unwrap();
+            }
+        }
+        let paths = vec![prjdir.join("vendor")];
+        (dir, prjdir, paths)
+    }
+
+    fn build(compression: Compression, prjdir: &Path, paths: &[PathBuf]) -> PathBuf {
+        let tarball = prjdir
+            .parent()
+            .unwrap()
+            .join(tarball_name(None, compression));
+        let options = compress::ArchiveOptions {
+            reproducible: true,
+            threads: Some(1),
+            ..Default::default()
+        };
+        match compression {
+            Compression::Gz => compress::targz(&tarball, prjdir, paths, options),
+            Compression::Zst => compress::tarzst(&tarball, prjdir, paths, options),
+            _ => unreachable!(),
+        }
+        .unwrap();
+        tarball
+    }
+
+    fn assert_compression_failed(result: Result<(), OBSCargoError>, what: &str) {
+        let err = result.expect_err(what);
+        assert!(
+            matches!(err.kind(), OBSCargoErrorKind::VendorCompressionFailed),
+            "{}: {}",
+            what,
+            err
+        );
+    }
+
+    #[test]
+    fn intact_tarball_verifies() {
+        let (_dir, prjdir, paths) = vendored();
+        for compression in [Compression::Gz, Compression::Zst] {
+            let tarball = build(compression, &prjdir, &paths);
+            verify_tarball(compression, &tarball, &paths).unwrap();
+        }
+    }
+
+    #[test]
+    fn truncated_tarball_rejected() {
+        let (_dir, prjdir, paths) = vendored();
+        for compression in [Compression::Gz, Compression::Zst] {
+            let tarball = build(compression, &prjdir, &paths);
+            let data = fs::read(&tarball).unwrap();
+            for len in [data.len() / 2, data.len() - 4] {
+                fs::write(&tarball, &data[..len]).unwrap();
+                assert_compression_failed(
+                    verify_tarball(compression, &tarball, &paths),
+                    &format!("{} truncated to {} bytes", tarball.display(), len),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn flipped_bytes_rejected() {
+        let (_dir, prjdir, paths) = vendored();
+        for compression in [Compression::Gz, Compression::Zst] {
+            let tarball = build(compression, &prjdir, &paths);
+            let data = fs::read(&tarball).unwrap();
+            // Inside the compressed data, and inside the checksum at the end.
+            for at in [data.len() / 2, data.len() - 2] {
+                let mut corrupt = data.clone();
+                corrupt[at] ^= 0xff;
+                fs::write(&tarball, &corrupt).unwrap();
+                assert_compression_failed(
+                    verify_tarball(compression, &tarball, &paths),
+                    &format!("{} with byte {} flipped", tarball.display(), at),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn entry_count_mismatch_rejected() {
+        let (_dir, prjdir, paths) = vendored();
+        let tarball = build(Compression::Zst, &prjdir, &paths);
+        let archived = compress::count_archive_entries(&paths).unwrap();
+        assert_eq!(archived, 2 * 20 + 3);
+        // A file that appeared after the tarball was written is missing from it.
+        fs::write(prjdir.join("vendor/a/late.rs"), "").unwrap();
+        let err = verify_tarball(Compression::Zst, &tarball, &paths).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            OBSCargoErrorKind::VendorCompressionFailed
+        ));
+        assert!(
+            err.to_string().contains(&format!(
+                "has {} entries but {} were archived",
+                archived,
+                archived + 1
+            )),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn oversized_tarball_removed() {
+        let (dir, prjdir, paths) = vendored();
+        let outdir = dir.path().join("out");
+        fs::create_dir_all(&outdir).unwrap();
+        for compression in ["zst", "gz", "not"] {
+            let opts = |max_size: &str| {
+                Opts::parse_from([
+                    "cargo_vendor",
+                    "--src",
+                    prjdir.to_str().unwrap(),
+                    "--outdir",
+                    outdir.to_str().unwrap(),
+                    "--compression",
+                    compression,
+                    "--max-archive-size",
+                    max_size,
+                ])
+            };
+            let err = compress(&opts("64"), &prjdir, None, &paths).unwrap_err();
+            assert!(
+                matches!(err.kind(), OBSCargoErrorKind::VendorCompressionFailed),
+                "{}",
+                err
+            );
+            assert!(err.to_string().contains("max-archive-size"), "{}", err);
+            assert_eq!(fs::read_dir(&outdir).unwrap().count(), 0, "{}", compression);
+
+            let tarball = compress(&opts("100000000"), &prjdir, None, &paths).unwrap();
+            assert!(tarball.is_file());
+            fs::remove_file(tarball).unwrap();
+        }
+    }
+
+    #[test]
+    fn version_changes_logged() {
+        let versions = |entries: &[(&str, &[&str])]| -> BTreeMap<String, Vec<String>> {
+            entries
+                .iter()
+                .map(|(name, versions)| {
+                    let versions = versions.iter().map(|v| v.to_string()).collect();
+                    (name.to_string(), versions)
+                })
+                .collect()
+        };
+        let before = versions(&[
+            ("hex", &["0.4.2"]),
+            ("old", &["1.0.0"]),
+            ("same", &["2.0.0"]),
+            ("syn", &["1.0.0", "2.0.0"]),
+        ]);
+        let after = versions(&[
+            ("hex", &["0.4.3"]),
+            ("new", &["0.1.0"]),
+            ("same", &["2.0.0"]),
+            ("syn", &["1.0.109", "2.0.0", "2.0.1"]),
+        ]);
+        let changes: Vec<String> = version_changes(&before, &after)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "hex 0.4.2 -> 0.4.3",
+                "new 0.1.0 (added)",
+                "old 1.0.0 (removed)",
+                "syn 1.0.0 -> 1.0.109",
+                "syn 2.0.1 (added)",
+            ]
+        );
+        assert!(version_changes(&before, &before).is_empty());
+    }
+
+    /// The cargo config printed by `cargo vendor`.
+    const VENDOR_CONFIG: &str = r#"[source.crates-io]
+replace-with = "vendored-sources"
+
+[source.vendored-sources]
+directory = "vendor"
+"#;
+
+    #[test]
+    fn config_fragments_merged() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.toml");
+        let second = dir.path().join("second.toml");
+        fs::write(
+            &first,
+            "[net]\noffline = false\n\n[source.crates-io]\nreplace-with = \"mirror\"\n",
+        )
+        .unwrap();
+        fs::write(&second, "[net]\noffline = true\n").unwrap();
+        let fragments = read_config_fragments(&[first, second]).unwrap();
+        let merged = merge_configs(VENDOR_CONFIG, None, fragments).unwrap();
+        let merged: toml::Table = toml::from_str(&merged).unwrap();
+        assert_eq!(merged["net"]["offline"].as_bool(), Some(true));
+        assert_eq!(
+            merged["source"]["crates-io"]["replace-with"].as_str(),
+            Some("vendored-sources")
+        );
+        assert_eq!(
+            merged["source"]["vendored-sources"]["directory"].as_str(),
+            Some("vendor")
+        );
+        assert_eq!(read_config_fragments(&[]).unwrap(), None);
+        let err = read_config_fragments(&[dir.path().join("missing.toml")]).unwrap_err();
+        assert!(matches!(err.kind(), OBSCargoErrorKind::VendorError));
+    }
+
+    #[test]
+    fn unchanged_config_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let cargo_config = dir.path().join("config.toml");
+        fs::write(
+            &cargo_config,
+            "# Written by hand\n[build]\njobs = 2\n\n[source.crates-io]\nreplace-with = \"old\"\n",
+        )
+        .unwrap();
+        let first =
+            merge_configs(VENDOR_CONFIG, read_existing_config(&cargo_config), None).unwrap();
+        let first_table: toml::Table = toml::from_str(&first).unwrap();
+        assert_eq!(first_table["build"]["jobs"].as_integer(), Some(2));
+        assert_eq!(
+            first_table["source"]["crates-io"]["replace-with"].as_str(),
+            Some("vendored-sources")
+        );
+        fs::write(&cargo_config, &first).unwrap();
+        let second =
+            merge_configs(VENDOR_CONFIG, read_existing_config(&cargo_config), None).unwrap();
+        assert_eq!(second, first);
+        // An up to date config is kept as written, comments included.
+        let hand_written = format!("# Written by hand\n{}", VENDOR_CONFIG);
+        fs::write(&cargo_config, &hand_written).unwrap();
+        let kept = merge_configs(VENDOR_CONFIG, read_existing_config(&cargo_config), None).unwrap();
+        assert_eq!(kept, hand_written);
+    }
+
+    #[test]
+    fn registry_mirror_replaces_crates_io() {
+        let mirror = "sparse+https://mirror.example.com/index/";
+        let config = use_registry_mirror(VENDOR_CONFIG, mirror).unwrap();
+        let config: toml::Table = toml::from_str(&config).unwrap();
+        let sources = &config["source"];
+        assert_eq!(
+            sources["crates-io"]["replace-with"].as_str(),
+            Some(REGISTRY_MIRROR_SOURCE)
+        );
+        assert_eq!(
+            sources[REGISTRY_MIRROR_SOURCE]["registry"].as_str(),
+            Some(mirror)
+        );
+        // Git sources still need the vendored sources.
+        assert_eq!(
+            sources["vendored-sources"]["directory"].as_str(),
+            Some("vendor")
+        );
+    }
+
+    #[test]
+    fn missing_git_source_added() {
+        let dir = tempfile::tempdir().unwrap();
+        let lockfile = dir.path().join("Cargo.lock");
+        fs::write(
+            &lockfile,
+            r#"version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["bar"]
+
+[[package]]
+name = "bar"
+version = "0.2.0"
+source = "git+https://github.com/foo/bar?rev=abc123#abc123def456"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            GitSource::parse("git+https://github.com/foo/bar?rev=abc123#abc123def456"),
+            Some(GitSource {
+                key: "git+https://github.com/foo/bar?rev=abc123".to_string(),
+                url: "https://github.com/foo/bar".to_string(),
+                reference: Some(("rev".to_string(), "abc123".to_string())),
+            })
+        );
+        assert_eq!(
+            GitSource::parse("registry+https://github.com/rust-lang/crates.io-index"),
+            None
+        );
+
+        let config = add_missing_git_sources(VENDOR_CONFIG, &[&lockfile]);
+        assert!(config.starts_with(VENDOR_CONFIG), "{}", config);
+        assert!(
+            config.ends_with(
+                r#"
+[source."git+https://github.com/foo/bar?rev=abc123"]
+git = "https://github.com/foo/bar"
+rev = "abc123"
+replace-with = "vendored-sources"
+"#
+            ),
+            "{}",
+            config
+        );
+        // A stanza cargo already emitted is not added twice.
+        assert_eq!(add_missing_git_sources(&config, &[&lockfile]), config);
+    }
+
+    #[test]
+    fn excluded_crates_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let vendor_dir = dir.path().join("vendor");
+        for (crate_dir, name, version) in [
+            ("foo", "foo", "0.2.0"),
+            ("foo-0.1.0", "foo", "0.1.0"),
+            ("foobar", "foobar", "1.0.0"),
+            ("bar", "bar", "1.0.0"),
+        ] {
+            let crate_dir = vendor_dir.join(crate_dir);
+            fs::create_dir_all(&crate_dir).unwrap();
+            fs::write(
+                crate_dir.join("Cargo.toml"),
+                format!("[package]\nname = {:?}\nversion = {:?}\n", name, version),
+            )
+            .unwrap();
+        }
+        exclude_crates(&vendor_dir, &["foo".to_string(), "missing".to_string()]).unwrap();
+        let mut kept: Vec<String> = fs::read_dir(&vendor_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        kept.sort();
+        assert_eq!(kept, ["bar", "foobar"]);
+    }
+
+    #[test]
+    fn checksum_in_sha256sum_format() {
+        use sha2::{Digest, Sha256};
+        let dir = tempfile::tempdir().unwrap();
+        let tarball = dir.path().join("vendor.tar.zst");
+        let data = b"not really a tarball";
+        fs::write(&tarball, data).unwrap();
+        let checksum = write_checksum(&tarball).unwrap();
+        assert_eq!(checksum, dir.path().join("vendor.tar.zst.sha256"));
+        let digest: String = Sha256::digest(data)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        assert_eq!(
+            fs::read_to_string(&checksum).unwrap(),
+            format!("{}  vendor.tar.zst\n", digest)
+        );
+        let status = std::process::Command::new("sha256sum")
+            .arg("--check")
+            .arg("--status")
+            .arg(checksum.file_name().unwrap())
+            .current_dir(dir.path())
+            .status();
+        // Not every build environment has coreutils.
+        if let Ok(status) = status {
+            assert!(status.success());
+        }
+    }
+
+    /// Writes `contents` to `dir/Cargo.toml`, creating `dir`.
+    fn manifest(dir: &Path, contents: &str) -> PathBuf {
+        fs::create_dir_all(dir).unwrap();
+        let manifest = dir.join("Cargo.toml");
+        fs::write(&manifest, contents).unwrap();
+        manifest
+    }
+
+    #[test]
+    fn project_info_from_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let package = manifest(
+            &dir.path().join("package"),
+            "[package]\nname = \"foo\"\nversion = \"1.2.3\"\n",
+        );
+        let info = project_info(&package).unwrap();
+        assert_eq!(
+            info,
+            ProjectInfo::Package {
+                name: "foo".to_string(),
+                version: Some("1.2.3".to_string()),
+            }
+        );
+        assert_eq!(info.to_string(), "foo 1.2.3");
+
+        let inherited = manifest(
+            &dir.path().join("inherited"),
+            "[package]\nname = \"foo\"\nversion.workspace = true\n\n\
+             [workspace]\n\n[workspace.package]\nversion = \"2.0.0\"\n",
+        );
+        assert_eq!(project_info(&inherited).unwrap().to_string(), "foo 2.0.0");
+
+        let root = dir.path().join("virtual");
+        let workspace = manifest(&root, "[workspace]\nmembers = [\"b\", \"a\"]\n");
+        manifest(&root.join("a"), "[package]\nname = \"alpha\"\n");
+        manifest(&root.join("b"), "[package]\nname = \"beta\"\n");
+        let info = project_info(&workspace).unwrap();
+        assert_eq!(
+            info,
+            ProjectInfo::Workspace {
+                members: vec!["alpha".to_string(), "beta".to_string()],
+            }
+        );
+        assert_eq!(info.to_string(), "workspace of alpha, beta");
+    }
+
+    #[test]
+    fn excluded_member_not_returned() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let workspace = manifest(
+            root,
+            "[workspace]\nmembers = [\"crates/*\"]\nexclude = [\"crates/skipped\"]\n",
+        );
+        let kept = manifest(&root.join("crates/kept"), "[package]\nname = \"kept\"\n");
+        manifest(
+            &root.join("crates/skipped"),
+            "[package]\nname = \"skipped\"\n",
+        );
+        assert_eq!(workspace_members(&workspace).unwrap(), [kept]);
+    }
+
+    #[test]
+    fn workspace_members_resolved() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let workspace = manifest(root, "[workspace]\nmembers = [\"cli\", \"crates/*\"]\n");
+        let cli = manifest(&root.join("cli"), "[package]\nname = \"cli\"\n");
+        let core = manifest(&root.join("crates/core"), "[package]\nname = \"core\"\n");
+        // Matched by the glob, but not a crate.
+        fs::create_dir_all(root.join("crates/docs")).unwrap();
+        assert_eq!(workspace_members(&workspace).unwrap(), [cli, core]);
+        assert!(is_workspace(&workspace).unwrap());
+        assert!(!is_workspace(&root.join("cli/Cargo.toml")).unwrap());
+    }
+
+    #[test]
+    fn mode_cargo_flags() {
+        let manifest = Path::new("project/Cargo.toml");
+        let no_extra: &[&Path] = &[];
+        for (mode, locked, offline) in [
+            (&[][..], false, false),
+            (&["--locked", "true"], true, false),
+            (&["--offline", "true"], false, true),
+            (&["--frozen", "true"], true, true),
+        ] {
+            let required = ["cargo_vendor", "--src", ".", "--outdir", "."];
+            let opts = Opts::parse_from(
+                required
+                    .iter()
+                    .chain(&["--respect-lockfile", "false"])
+                    .chain(mode),
+            );
+            assert_eq!(opts.is_locked(), locked, "{:?}", mode);
+            assert_eq!(opts.is_offline(), offline, "{:?}", mode);
+            let (subcommand, args) = vendor_args(&opts, manifest, no_extra);
+            assert_eq!(subcommand, "vendor");
+            let mut expected = vec![
+                "--manifest-path",
+                "project/Cargo.toml",
+                "--versioned-dirs",
+                "-vv",
+            ];
+            if locked {
+                expected.push("--locked");
+            }
+            assert_eq!(args, expected, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn explicit_platforms_replace_filter() {
+        let parse = |args: &[&str]| {
+            let required = ["cargo_vendor", "--src", ".", "--outdir", "."];
+            Opts::parse_from(required.iter().chain(args))
+        };
+        assert!(filter_platforms(&parse(&[])).is_empty());
+        assert_eq!(
+            filter_platforms(&parse(&["--filter", "true"])),
+            [
+                "*-unknown-linux-gnu",
+                "wasm32-wasi",
+                "wasm32-unknown-unknown"
+            ]
+        );
+        let explicit = [
+            "--filter-platform",
+            "x86_64-unknown-linux-gnu",
+            "--filter-platform",
+            "aarch64-unknown-linux-gnu",
+        ];
+        let expected = ["x86_64-unknown-linux-gnu", "aarch64-unknown-linux-gnu"];
+        assert_eq!(filter_platforms(&parse(&explicit)), expected);
+        let with_filter: Vec<&str> = explicit
+            .iter()
+            .chain(&["--filter", "true"])
+            .copied()
+            .collect();
+        let opts = parse(&with_filter);
+        assert_eq!(filter_platforms(&opts), expected);
+        let (subcommand, args) = vendor_args(&opts, Path::new("Cargo.toml"), &[] as &[&Path]);
+        assert_eq!(subcommand, "vendor-filterer");
+        let platforms: Vec<&OsString> = args
+            .iter()
+            .filter(|arg| arg.to_string_lossy().starts_with("--platform="))
+            .collect();
+        assert_eq!(
+            platforms,
+            [
+                "--platform=x86_64-unknown-linux-gnu",
+                "--platform=aarch64-unknown-linux-gnu"
+            ]
+        );
+    }
+
+    #[test]
+    fn no_dev_deps_filterer_args() {
+        let required = ["cargo_vendor", "--src", ".", "--outdir", "."];
+        let manifest = Path::new("project/Cargo.toml");
+        let extra = [Path::new("project/tool/Cargo.toml")];
+        let opts = Opts::parse_from(required.iter().chain(&["--no-dev-deps", "true"]));
+        let (subcommand, args) = vendor_args(&opts, manifest, &extra);
+        assert_eq!(subcommand, "vendor-filterer");
+        assert_eq!(
+            args,
+            [
+                "--manifest-path",
+                "project/Cargo.toml",
+                "--versioned-dirs",
+                "--sync",
+                "project/tool/Cargo.toml",
+                "--keep-dep-kinds=no-dev",
+                "--all-features",
+                "--format=dir",
+            ]
+        );
+        // Platforms to filter by come first.
+        let opts = Opts::parse_from(required.iter().chain(&[
+            "--no-dev-deps",
+            "true",
+            "--filter-platform",
+            "x86_64-unknown-linux-gnu",
+            "--vendor-dir-name",
+            "third-party",
+        ]));
+        let (subcommand, args) = vendor_args(&opts, manifest, &[] as &[&Path]);
+        assert_eq!(subcommand, "vendor-filterer");
+        assert_eq!(
+            args,
+            [
+                "--manifest-path",
+                "project/Cargo.toml",
+                "--versioned-dirs",
+                "--platform=x86_64-unknown-linux-gnu",
+                "--keep-dep-kinds=no-dev",
+                "--all-features",
+                "--format=dir",
+                "third-party",
+            ]
+        );
+    }
+
+    #[test]
+    fn tagged_tarball_names() {
+        let names: Vec<String> = [None, Some("foo"), Some("1.2"), Some("1.2.3")]
+            .into_iter()
+            .map(|tag| tarball_name(tag, Compression::Zst))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "vendor.tar.zst",
+                "vendor-foo.tar.zst",
+                "vendor-1.2.tar.zst",
+                "vendor-1.2.3.tar.zst",
+            ]
+        );
+        // All of them and their checksums can be written to the same outdir.
+        let dir = tempfile::tempdir().unwrap();
+        for name in &names {
+            let tarball = dir.path().join(name);
+            fs::write(&tarball, name).unwrap();
+            write_checksum(&tarball).unwrap();
+        }
+        for name in &names {
+            let tarball = dir.path().join(name);
+            assert_eq!(fs::read_to_string(&tarball).unwrap(), *name);
+            let checksum = fs::read_to_string(sidecar_path(&tarball, ".sha256")).unwrap();
+            assert!(checksum.ends_with(&format!("  {}\n", name)), "{}", checksum);
+        }
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2 * names.len());
+        assert_eq!(
+            tarball_name(Some("1.2"), Compression::Gz),
+            "vendor-1.2.tar.gz"
+        );
+        assert_eq!(
+            tarball_name(Some("1.2"), Compression::Not),
+            "vendor-1.2.tar"
+        );
+    }
+
+    #[test]
+    fn vendor_stats_counted() {
+        let dir = tempfile::tempdir().unwrap();
+        let vendor_dir = dir.path().join("vendor");
+        let a = "[package]\nname = \"a\"\n";
+        let b = "[package]\nname = \"b\"\nversion = \"2.0.0\"\n";
+        manifest(&vendor_dir.join("a-1.0.0"), a);
+        manifest(&vendor_dir.join("b-2.0.0"), b);
+        fs::create_dir_all(vendor_dir.join("b-2.0.0/src")).unwrap();
+        fs::write(vendor_dir.join("b-2.0.0/src/lib.rs"), "0123456789").unwrap();
+        // Not a crate, but its size counts.
+        fs::create_dir_all(vendor_dir.join("stray")).unwrap();
+        fs::write(vendor_dir.join("stray/file"), "xyz").unwrap();
+        let tarball = dir.path().join("vendor.tar.zst");
+        fs::write(&tarball, [0u8; 42]).unwrap();
+
+        let required = ["cargo_vendor", "--src", ".", "--outdir", "."];
+        let stats = vendor_stats(&Opts::parse_from(required), &vendor_dir, &tarball).unwrap();
+        assert_eq!(
+            stats,
+            VendorStats {
+                crates: 2,
+                uncompressed_bytes: (a.len() + b.len() + 10 + 3) as u64,
+                compressed_bytes: 42,
+            }
+        );
+    }
+}
@@ -0,0 +1,84 @@
+// SPDX-License-Identifier: MPL-2.0
+
+// Copyright (C) 2024 To all Contributors of this project listed in CONTRIBUTORS.md
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Helpers shared by the tests are not covered by clippy.toml.
+#![allow(clippy::unwrap_used)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use obs_service_cargo::cli::Opts;
+
+/// Leftover working directories in `scratch`.
+fn leftovers(scratch: &Path) -> Vec<PathBuf> {
+    fs::read_dir(scratch)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.file_name().is_some_and(|name| {
+                name.to_string_lossy()
+                    .starts_with(".obs-service-cargo-vendor")
+            })
+        })
+        .collect()
+}
+
+fn vendor_with_scratch(
+    src: &Path,
+    root: &Path,
+) -> (PathBuf, obs_service_cargo::errors::OBSCargoError) {
+    let scratch = root.join("scratch");
+    let outdir = root.join("out");
+    fs::create_dir_all(&scratch).unwrap();
+    fs::create_dir_all(&outdir).unwrap();
+    let opts = Opts::try_parse_from([
+        "cargo_vendor",
+        "--src",
+        src.to_str().unwrap(),
+        "--outdir",
+        outdir.to_str().unwrap(),
+        "--tmpdir",
+        scratch.to_str().unwrap(),
+    ])
+    .unwrap();
+    let err = obs_service_cargo::vendor(&opts).unwrap_err();
+    (scratch, err)
+}
+
+#[test]
+fn workdir_removed_after_missing_manifest() {
+    let root = tempfile::tempdir().unwrap();
+    let src = root.path().join("project");
+    fs::create_dir_all(src.join("src")).unwrap();
+    fs::write(src.join("src/main.rs"), "fn main() {}\n").unwrap();
+    let (scratch, err) = vendor_with_scratch(&src, root.path());
+    assert_eq!(err.kind().exit_code(), 9, "{}", err);
+    assert_eq!(leftovers(&scratch), Vec::<PathBuf>::new());
+}
+
+#[test]
+fn workdir_removed_after_failed_extraction() {
+    let root = tempfile::tempdir().unwrap();
+    let src = root.path().join("project-1.0.tar.gz");
+    let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    let manifest = "[package]\nname = \"project\"\nversion = \"1.0.0\"\n".repeat(64);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    builder
+        .append_data(&mut header, "project-1.0/Cargo.toml", manifest.as_bytes())
+        .unwrap();
+    let tarball = builder.into_inner().unwrap().finish().unwrap();
+    // Cut the stream in half so extraction fails after the workdir exists.
+    fs::write(&src, &tarball[..tarball.len() / 2]).unwrap();
+    let (scratch, err) = vendor_with_scratch(&src, root.path());
+    assert_eq!(err.kind().exit_code(), 6, "{}", err);
+    assert_eq!(leftovers(&scratch), Vec::<PathBuf>::new());
+}
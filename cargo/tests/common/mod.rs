@@ -0,0 +1,237 @@
+// SPDX-License-Identifier: MPL-2.0
+
+// Copyright (C) 2024 To all Contributors of this project listed in CONTRIBUTORS.md
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Helpers for running `cargo_vendor` against a mock cargo, so the tests
+//! need neither the network nor a cargo cache.
+
+// Not every test uses every helper.
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+const MANIFEST: &str = r#"[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+hex = "0.4"
+"#;
+
+const LOCKFILE: &str = r#"version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "hex",
+]
+
+[[package]]
+name = "hex"
+version = "0.4.3"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "7f24254aa9a54b5c858eaee2f5bccdb46aaf0e486a595ed5fd8f86ba55232a70"
+"#;
+
+/// Writes a project depending on `hex`, with its lockfile, to `dir`.
+pub fn project(dir: &Path) -> PathBuf {
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join("Cargo.toml"), MANIFEST).unwrap();
+    fs::write(dir.join("Cargo.lock"), LOCKFILE).unwrap();
+    fs::write(dir.join("src/lib.rs"), "").unwrap();
+    dir.to_path_buf()
+}
+
+/// Writes a project like `project` as `app-0.1.0/` into a gzip-compressed
+/// tarball at `path`.
+pub fn project_tarball(path: &Path) {
+    let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    let mut builder = tar::Builder::new(encoder);
+    for (name, data) in [
+        ("app-0.1.0/Cargo.toml", MANIFEST),
+        ("app-0.1.0/Cargo.lock", LOCKFILE),
+        ("app-0.1.0/src/lib.rs", ""),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, name, data.as_bytes())
+            .unwrap();
+    }
+    let tarball = builder.into_inner().unwrap().finish().unwrap();
+    fs::write(path, tarball).unwrap();
+}
+
+/// The date `days` after the epoch as `YYYY-MM-DD`.
+fn civil_date(days: i64) -> String {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Writes an advisory database to `dir` that is fresh enough for the audit.
+/// Its only advisory is for a crate the project does not use.
+pub fn advisory_db(dir: &Path) -> PathBuf {
+    let today = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        / 86400;
+    let crate_dir = dir.join("crates/unused");
+    fs::create_dir_all(&crate_dir).unwrap();
+    fs::write(
+        crate_dir.join("RUSTSEC-2024-0001.md"),
+        format!(
+            "```toml\n[advisory]\nid = \"RUSTSEC-2024-0001\"\npackage = \"unused\"\ndate = \"{}\"\n\n[versions]\npatched = [\">= 1.0.0\"]\n```\n\n# Unused is bad\n\nDescription.\n",
+            civil_date(today as i64)
+        ),
+    )
+    .unwrap();
+    dir.to_path_buf()
+}
+
+/// Writes a mock cargo to `dir`. Every call is logged to `dir/calls` as its
+/// arguments and the value of `CARGO_NET_OFFLINE`. `vendor` and
+/// `vendor-filterer` create `hex` in the vendor directory and print the
+/// cargo config like cargo does. Any subcommand fails with the contents of
+/// `dir/fail-<subcommand>` on stderr if that file exists, see `fail`.
+pub fn mock_cargo(dir: &Path) -> PathBuf {
+    fs::create_dir_all(dir).unwrap();
+    let script = dir.join("cargo");
+    fs::write(
+        &script,
+        format!(
+            r#"#!/bin/sh
+echo "$* | offline=$CARGO_NET_OFFLINE" >> '{dir}/calls'
+[ "$1" = --offline ] && shift
+sub=$1
+shift
+if [ -e '{dir}/fail-'"$sub" ]; then
+    cat '{dir}/fail-'"$sub" >&2
+    exit 101
+fi
+case "$sub" in
+vendor | vendor-filterer)
+    vendor_dir=vendor
+    skip=
+    for arg in "$@"; do
+        if [ -n "$skip" ]; then
+            skip=
+            continue
+        fi
+        case "$arg" in
+        --manifest-path | --sync) skip=1 ;;
+        -*) ;;
+        *) vendor_dir=$arg ;;
+        esac
+    done
+    mkdir -p "$vendor_dir/hex-0.4.3/src"
+    printf '[package]\nname = "hex"\nversion = "0.4.3"\n' > "$vendor_dir/hex-0.4.3/Cargo.toml"
+    echo 'pub fn encode() {{}}' > "$vendor_dir/hex-0.4.3/src/lib.rs"
+    echo '{{"files":{{}},"package":null}}' > "$vendor_dir/hex-0.4.3/.cargo-checksum.json"
+    printf '[source.crates-io]\nreplace-with = "vendored-sources"\n\n[source.vendored-sources]\ndirectory = "%s"\n' "$vendor_dir"
+    ;;
+esac
+"#,
+            dir = dir.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    script
+}
+
+/// Makes `cargo <subcommand>` of the mock cargo in `dir` fail with `stderr`.
+pub fn fail(dir: &Path, subcommand: &str, stderr: &str) {
+    fs::write(dir.join(format!("fail-{}", subcommand)), stderr).unwrap();
+}
+
+/// The calls the mock cargo in `dir` got, one per line.
+pub fn calls(dir: &Path) -> Vec<String> {
+    fs::read_to_string(dir.join("calls"))
+        .unwrap_or_default()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Runs `cargo_vendor` with `args`, using `cargo` as cargo.
+pub fn cargo_vendor(cargo: &Path, args: &[&str]) -> Output {
+    cargo_vendor_with_stdin(cargo, args, &[])
+}
+
+/// Like `cargo_vendor`, with `stdin` piped in.
+pub fn cargo_vendor_with_stdin(cargo: &Path, args: &[&str], stdin: &[u8]) -> Output {
+    use std::io::Write;
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cargo_vendor"))
+        .args(args)
+        .env("CARGO", cargo)
+        .env_remove("RUST_LOG")
+        .env_remove("NO_COLOR")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut pipe = child.stdin.take().unwrap();
+    pipe.write_all(stdin).unwrap();
+    drop(pipe);
+    child.wait_with_output().unwrap()
+}
+
+/// Standard output and error of a run, for assertion messages.
+pub fn logs(output: &Output) -> String {
+    format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
+
+/// The entry paths of the zstd-compressed tarball at `tarball`.
+pub fn tarball_entries(tarball: &Path) -> Vec<String> {
+    let mut archive =
+        tar::Archive::new(zstd::Decoder::new(fs::File::open(tarball).unwrap()).unwrap());
+    archive
+        .entries()
+        .unwrap()
+        .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+        .collect()
+}
+
+/// The contents of `path` inside the zstd-compressed tarball at `tarball`.
+pub fn tarball_file(tarball: &Path, path: &str) -> String {
+    let mut archive =
+        tar::Archive::new(zstd::Decoder::new(fs::File::open(tarball).unwrap()).unwrap());
+    let mut entry = archive
+        .entries()
+        .unwrap()
+        .map(Result::unwrap)
+        .find(|entry| entry.path().unwrap().display().to_string() == path)
+        .unwrap();
+    let mut contents = String::new();
+    entry.read_to_string(&mut contents).unwrap();
+    contents
+}
+
+pub fn path(p: &Path) -> &str {
+    p.to_str().unwrap()
+}
@@ -0,0 +1,59 @@
+// SPDX-License-Identifier: MPL-2.0
+
+// Copyright (C) 2024 To all Contributors of this project listed in CONTRIBUTORS.md
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Helpers shared by the tests are not covered by clippy.toml.
+#![allow(clippy::unwrap_used)]
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn cargo_vendor(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cargo_vendor"))
+        .args(args)
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap()
+}
+
+fn path(p: &Path) -> &str {
+    p.to_str().unwrap()
+}
+
+#[test]
+fn invalid_parameters() {
+    let output = cargo_vendor(&["--compression", "rar"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn missing_source() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = cargo_vendor(&[
+        "--src",
+        path(&dir.path().join("missing.tar.gz")),
+        "--outdir",
+        path(dir.path()),
+    ]);
+    assert_eq!(output.status.code(), Some(6));
+}
+
+#[test]
+fn missing_manifest() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = dir.path().join("project");
+    fs::create_dir_all(src.join("src")).unwrap();
+    fs::write(src.join("src/main.rs"), "fn main() {}\n").unwrap();
+    let output = cargo_vendor(&["--src", path(&src), "--outdir", path(dir.path())]);
+    assert_eq!(
+        output.status.code(),
+        Some(9),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
@@ -0,0 +1,118 @@
+// SPDX-License-Identifier: MPL-2.0
+
+// Copyright (C) 2024 To all Contributors of this project listed in CONTRIBUTORS.md
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Helpers shared by the tests are not covered by clippy.toml.
+#![allow(clippy::unwrap_used)]
+
+mod common;
+
+use std::fs;
+use std::process::{Command, Output};
+
+use common::{advisory_db, logs, mock_cargo, path, project};
+
+/// Vendors a project with a mock cargo and `args` added.
+fn vendor_run(args: &[&str]) -> Output {
+    let dir = tempfile::tempdir().unwrap();
+    let src = project(&dir.path().join("app"));
+    let db = advisory_db(&dir.path().join("db"));
+    let cargo = mock_cargo(&dir.path().join("mock"));
+    let outdir = dir.path().join("out");
+    fs::create_dir(&outdir).unwrap();
+    let required = [
+        "--src",
+        path(&src),
+        "--outdir",
+        path(&outdir),
+        "--advisory-db",
+        path(&db),
+    ];
+    let args: Vec<&str> = required.iter().chain(args).copied().collect();
+    let output = common::cargo_vendor(&cargo, &args);
+    assert!(output.status.success(), "{}", logs(&output));
+    assert!(outdir.join("vendor.tar.zst").is_file());
+    output
+}
+
+#[test]
+fn bash_completion() {
+    // No `--src` or `--outdir`, completions do not need them.
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo_vendor"))
+        .args(["--generate-completion", "bash"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", logs(&output));
+    let completion = String::from_utf8(output.stdout).unwrap();
+    assert!(!completion.is_empty());
+    assert!(completion.contains("--compression"), "{}", completion);
+    assert!(completion.contains("cargo_vendor"), "{}", completion);
+}
+
+#[test]
+fn quiet_suppresses_info() {
+    let output = logs(&vendor_run(&[]));
+    assert!(output.contains(" INFO "), "{}", output);
+    assert!(
+        output.contains("Starting OBS Service Cargo Vendor"),
+        "{}",
+        output
+    );
+    assert!(output.contains("rewritten in rust"), "{}", output);
+
+    let output = logs(&vendor_run(&["--quiet"]));
+    assert!(!output.contains(" INFO "), "{}", output);
+    assert!(
+        !output.contains("Starting OBS Service Cargo Vendor"),
+        "{}",
+        output
+    );
+    assert!(!output.contains("rewritten in rust"), "{}", output);
+}
+
+#[test]
+fn color_never_has_no_escapes() {
+    let escape = |output: &Output| logs(output).contains('\x1b');
+    assert!(!escape(&vendor_run(&["--color", "never"])));
+    // Not a terminal, so `auto` does not color either.
+    assert!(!escape(&vendor_run(&[])));
+    assert!(escape(&vendor_run(&["--color", "always"])));
+}
+
+#[test]
+fn json_log_lines() {
+    let output = vendor_run(&["--log-format", "json"]);
+    let events: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let started = events
+        .iter()
+        .find(|event| {
+            event["fields"]["message"]
+                .as_str()
+                .is_some_and(|m| m.contains("Starting OBS Service Cargo Vendor"))
+        })
+        .unwrap();
+    assert_eq!(started["level"], "INFO");
+    assert!(started["timestamp"].is_string(), "{}", started);
+    assert!(
+        started["fields"]["src"]
+            .as_str()
+            .is_some_and(|src| src.ends_with("/app")),
+        "{}",
+        started
+    );
+    assert!(started["fields"]["outdir"].is_string(), "{}", started);
+    let stats = events
+        .iter()
+        .find(|event| event["fields"]["crates"].is_u64())
+        .unwrap();
+    assert_eq!(stats["fields"]["crates"], 1);
+    assert!(stats["fields"]["compressed_bytes"].as_u64() > Some(0));
+}
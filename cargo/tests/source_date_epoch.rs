@@ -0,0 +1,61 @@
+// SPDX-License-Identifier: MPL-2.0
+
+// Copyright (C) 2024 To all Contributors of this project listed in CONTRIBUTORS.md
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Helpers shared by the tests are not covered by clippy.toml.
+#![allow(clippy::unwrap_used)]
+
+// The only test in this binary, as it changes the environment of the process.
+
+use std::fs;
+use std::path::Path;
+
+use obs_service_cargo::utils::compress::{self, ArchiveOptions};
+
+fn mtimes(tarball: &Path) -> Vec<u64> {
+    let decoder = zstd::Decoder::new(fs::File::open(tarball).unwrap()).unwrap();
+    tar::Archive::new(decoder)
+        .entries()
+        .unwrap()
+        .map(|entry| entry.unwrap().header().mtime().unwrap())
+        .collect()
+}
+
+#[test]
+fn entries_use_source_date_epoch() {
+    let dir = tempfile::tempdir().unwrap();
+    let vendor = dir.path().join("vendor");
+    fs::create_dir_all(vendor.join("foo/src")).unwrap();
+    fs::write(vendor.join("foo/src/lib.rs"), "").unwrap();
+    let tarball = dir.path().join("vendor.tar.zst");
+    let archive = |reproducible: bool| {
+        let options = ArchiveOptions {
+            reproducible,
+            ..Default::default()
+        };
+        compress::tarzst(&tarball, dir.path(), &[&vendor], options).unwrap();
+        mtimes(&tarball)
+    };
+
+    std::env::set_var("SOURCE_DATE_EPOCH", "1700000000");
+    assert_eq!(compress::source_date_epoch(), Some(1_700_000_000));
+    for reproducible in [true, false] {
+        let mtimes = archive(reproducible);
+        assert_eq!(mtimes.len(), 4);
+        assert!(
+            mtimes.iter().all(|mtime| *mtime == 1_700_000_000),
+            "{:?}",
+            mtimes
+        );
+    }
+
+    // An invalid value is ignored.
+    std::env::set_var("SOURCE_DATE_EPOCH", "yesterday");
+    assert_eq!(compress::source_date_epoch(), None);
+    assert!(archive(true).iter().all(|mtime| *mtime == 0));
+    assert!(archive(false).iter().all(|mtime| *mtime > 1_700_000_000));
+}
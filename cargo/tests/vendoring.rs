@@ -0,0 +1,335 @@
+// SPDX-License-Identifier: MPL-2.0
+
+// Copyright (C) 2024 To all Contributors of this project listed in CONTRIBUTORS.md
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Helpers shared by the tests are not covered by clippy.toml.
+#![allow(clippy::unwrap_used)]
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+use common::{advisory_db, logs, mock_cargo, path, project};
+
+/// A project, an advisory database and a mock cargo in a tempdir, with an
+/// empty outdir.
+struct Setup {
+    dir: tempfile::TempDir,
+    src: PathBuf,
+    db: PathBuf,
+    mock: PathBuf,
+    cargo: PathBuf,
+    outdir: PathBuf,
+}
+
+impl Setup {
+    fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let outdir = dir.path().join("out");
+        fs::create_dir(&outdir).unwrap();
+        let mock = dir.path().join("mock");
+        Setup {
+            src: project(&dir.path().join("app")),
+            db: advisory_db(&dir.path().join("db")),
+            cargo: mock_cargo(&mock),
+            mock,
+            outdir,
+            dir,
+        }
+    }
+
+    /// Runs `cargo_vendor` on the project with `args` added.
+    fn run(&self, args: &[&str]) -> Output {
+        let required = [
+            "--src",
+            path(&self.src),
+            "--outdir",
+            path(&self.outdir),
+            "--advisory-db",
+            path(&self.db),
+        ];
+        let args: Vec<&str> = required.iter().chain(args).copied().collect();
+        common::cargo_vendor(&self.cargo, &args)
+    }
+
+    fn tarball(&self) -> PathBuf {
+        self.outdir.join("vendor.tar.zst")
+    }
+}
+
+#[test]
+fn vendor_dir_renamed() {
+    let setup = Setup::new();
+    let output = setup.run(&["--vendor-dir-name", "third-party"]);
+    assert!(output.status.success(), "{}", logs(&output));
+    let entries = common::tarball_entries(&setup.tarball());
+    assert!(
+        entries
+            .iter()
+            .any(|e| e == "third-party/hex-0.4.3/Cargo.toml"),
+        "{:?}",
+        entries
+    );
+    assert!(
+        !entries.iter().any(|e| e.starts_with("vendor/")),
+        "{:?}",
+        entries
+    );
+    let config = common::tarball_file(&setup.tarball(), ".cargo/config.toml");
+    assert!(config.contains("directory = \"third-party\""), "{}", config);
+}
+
+#[test]
+fn dry_run_writes_nothing() {
+    let setup = Setup::new();
+    let output = setup.run(&["--dry-run", "true"]);
+    assert!(output.status.success(), "{}", logs(&output));
+    assert!(logs(&output).contains("Would create"), "{}", logs(&output));
+    assert_eq!(fs::read_dir(&setup.outdir).unwrap().count(), 0);
+    assert_eq!(common::calls(&setup.mock), Vec::<String>::new());
+    assert!(setup.dir.path().join("app/Cargo.lock").is_file());
+}
+
+#[test]
+fn tarball_from_stdin() {
+    let setup = Setup::new();
+    let tarball = setup.dir.path().join("app-0.1.0.tar.gz");
+    common::project_tarball(&tarball);
+    let scratch = setup.dir.path().join("scratch");
+    fs::create_dir(&scratch).unwrap();
+    let output = common::cargo_vendor_with_stdin(
+        &setup.cargo,
+        &[
+            "--src",
+            "-",
+            "--outdir",
+            path(&setup.outdir),
+            "--advisory-db",
+            path(&setup.db),
+            "--tmpdir",
+            path(&scratch),
+        ],
+        &fs::read(&tarball).unwrap(),
+    );
+    assert!(output.status.success(), "{}", logs(&output));
+    assert!(
+        logs(&output).contains("Reading source tarball from stdin"),
+        "{}",
+        logs(&output)
+    );
+    let entries = common::tarball_entries(&setup.tarball());
+    assert!(
+        entries.iter().any(|e| e == "vendor/hex-0.4.3/Cargo.toml"),
+        "{:?}",
+        entries
+    );
+    // Neither the buffered tarball nor the workdir is left behind.
+    assert_eq!(fs::read_dir(&scratch).unwrap().count(), 0);
+}
+
+#[test]
+fn cargo_stderr_in_error() {
+    let setup = Setup::new();
+    let mut stderr: String = (1..=25).map(|n| format!("noise line {}\n", n)).collect();
+    stderr.push_str("error: failed to select a version for the requirement `hex = \"^9\"`\n");
+    common::fail(&setup.mock, "vendor", &stderr);
+    let output = setup.run(&[]);
+    assert_eq!(output.status.code(), Some(6), "{}", logs(&output));
+    let logs = logs(&output);
+    // The binary reports only the tail of cargo's stderr, one detail per line.
+    let details: Vec<&str> = logs
+        .lines()
+        .filter(|line| line.contains(" ERROR cargo_vendor:"))
+        .filter_map(|line| line.split_once(":   ").map(|(_, detail)| detail))
+        .collect();
+    assert_eq!(details.len(), 20, "{}", logs);
+    assert_eq!(details[0], "noise line 7", "{}", logs);
+    assert_eq!(
+        details[19], "error: failed to select a version for the requirement `hex = \"^9\"`",
+        "{}",
+        logs
+    );
+    assert!(!setup.tarball().exists());
+}
+
+#[test]
+fn workdir_under_tmpdir() {
+    let setup = Setup::new();
+    let scratch = setup.dir.path().join("scratch");
+    fs::create_dir(&scratch).unwrap();
+    let output = setup.run(&["--tmpdir", path(&scratch)]);
+    assert!(output.status.success(), "{}", logs(&output));
+    // cargo vendored the copy of the project in the workdir.
+    let calls = common::calls(&setup.mock);
+    let vendor = calls
+        .iter()
+        .find(|call| call.starts_with("vendor "))
+        .unwrap();
+    let workdir = format!("{}/.obs-service-cargo-vendor", path(&scratch));
+    assert!(
+        vendor.contains(&format!("--manifest-path {}", workdir)),
+        "{:?}",
+        calls
+    );
+    assert!(!vendor.contains(path(&setup.src)), "{:?}", calls);
+    // And it is removed afterwards.
+    assert_eq!(fs::read_dir(&scratch).unwrap().count(), 0);
+}
+
+#[test]
+fn kept_workdir() {
+    let setup = Setup::new();
+    let workdir = setup.dir.path().join("work/kept");
+    let output = setup.run(&["--keep-workdir", path(&workdir)]);
+    assert!(output.status.success(), "{}", logs(&output));
+    assert!(setup.tarball().is_file());
+    let vendored: Vec<PathBuf> = fs::read_dir(&workdir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path().join("vendor/hex-0.4.3/Cargo.toml"))
+        .filter(|manifest| manifest.is_file())
+        .collect();
+    assert_eq!(vendored.len(), 1, "{:?}", fs::read_dir(&workdir));
+
+    // Leftovers of the first run could be taken for sources.
+    let output = setup.run(&["--keep-workdir", path(&workdir)]);
+    assert_eq!(output.status.code(), Some(6), "{}", logs(&output));
+    let message = format!("working directory {} is not empty", path(&workdir));
+    assert!(logs(&output).contains(&message), "{}", logs(&output));
+    assert!(vendored[0].is_file());
+}
+
+#[test]
+fn stale_lockfile_rejected() {
+    let setup = Setup::new();
+    common::fail(
+        &setup.mock,
+        "metadata",
+        "error: the lock file needs to be updated but --locked was passed to prevent this\n",
+    );
+    let output = setup.run(&["--locked", "true"]);
+    assert_eq!(output.status.code(), Some(6), "{}", logs(&output));
+    let logs = logs(&output);
+    let metadata = common::calls(&setup.mock)
+        .into_iter()
+        .find(|call| call.starts_with("metadata --locked"))
+        .unwrap();
+    let manifest = metadata
+        .split(' ')
+        .skip_while(|arg| *arg != "--manifest-path")
+        .nth(1)
+        .unwrap();
+    assert!(manifest.ends_with("/Cargo.toml"), "{}", metadata);
+    assert!(
+        logs.contains(&format!(
+            "lockfile for {} is missing or needs to be updated",
+            manifest
+        )),
+        "{}",
+        logs
+    );
+    assert!(
+        logs.contains("the lock file needs to be updated but --locked was passed"),
+        "{}",
+        logs
+    );
+    assert!(!setup.tarball().exists());
+}
+
+#[test]
+fn offline_reaches_cargo() {
+    let setup = Setup::new();
+    let output = setup.run(&["--offline", "true"]);
+    assert!(output.status.success(), "{}", logs(&output));
+    let calls = common::calls(&setup.mock);
+    assert!(calls
+        .iter()
+        .any(|call| call.starts_with("--offline vendor ")));
+    for call in &calls {
+        assert!(call.starts_with("--offline "), "{:?}", calls);
+        assert!(call.ends_with(" | offline=true"), "{:?}", calls);
+    }
+
+    let setup = Setup::new();
+    let output = setup.run(&[]);
+    assert!(output.status.success(), "{}", logs(&output));
+    let calls = common::calls(&setup.mock);
+    assert!(!calls.is_empty());
+    for call in &calls {
+        assert!(!call.contains("--offline"), "{:?}", calls);
+        assert!(call.ends_with(" | offline="), "{:?}", calls);
+    }
+}
+
+#[test]
+fn config_format_file_names() {
+    for (format, written, absent) in [
+        ("toml", ".cargo/config.toml", ".cargo/config"),
+        ("legacy", ".cargo/config", ".cargo/config.toml"),
+    ] {
+        let setup = Setup::new();
+        let output = setup.run(&["--config-format", format]);
+        assert!(output.status.success(), "{}", logs(&output));
+        let entries = common::tarball_entries(&setup.tarball());
+        assert!(entries.iter().any(|e| e == written), "{:?}", entries);
+        assert!(!entries.iter().any(|e| e == absent), "{:?}", entries);
+        let config: toml::Table = common::tarball_file(&setup.tarball(), written)
+            .parse()
+            .unwrap();
+        assert_eq!(
+            config["source"]["vendored-sources"]["directory"].as_str(),
+            Some("vendor"),
+            "{}",
+            config
+        );
+        assert_eq!(
+            config["source"]["crates-io"]["replace-with"].as_str(),
+            Some("vendored-sources"),
+            "{}",
+            config
+        );
+    }
+}
+
+#[test]
+fn only_vendor_tree_archived() {
+    let setup = Setup::new();
+    let tarball = setup.dir.path().join("app-0.1.0.tar.gz");
+    common::project_tarball(&tarball);
+    let source = fs::read(&tarball).unwrap();
+    let output = common::cargo_vendor(
+        &setup.cargo,
+        &[
+            "--src",
+            path(&tarball),
+            "--outdir",
+            path(&setup.outdir),
+            "--advisory-db",
+            path(&setup.db),
+        ],
+    );
+    assert!(output.status.success(), "{}", logs(&output));
+    let entries = common::tarball_entries(&setup.tarball());
+    for entry in &entries {
+        assert!(
+            Path::new(entry).starts_with("vendor")
+                || [".cargo/config.toml", "Cargo.lock"].contains(&entry.as_str()),
+            "{:?}",
+            entries
+        );
+    }
+    assert!(entries.iter().any(|e| e == "Cargo.lock"), "{:?}", entries);
+    assert!(
+        entries.iter().any(|e| e == "vendor/hex-0.4.3/src/lib.rs"),
+        "{:?}",
+        entries
+    );
+    // The source tarball is left alone.
+    assert_eq!(fs::read(&tarball).unwrap(), source);
+    assert_eq!(fs::read_dir(&setup.outdir).unwrap().count(), 1);
+}